@@ -0,0 +1,122 @@
+use bit::{Bit, Bits};
+
+/// A volume envelope, which steps the volume up or down periodically from
+/// its initial value.
+pub struct Envelope {
+    // The envelope register: the step time in bits 0-2, the direction in
+    // bit 3 (set to increase) and the initial volume in bits 4-7
+    register: u8,
+    volume: u8,
+    timer: u8,
+}
+
+impl Envelope {
+    pub fn new() -> Envelope {
+        Envelope {
+            register: 0,
+            volume: 0,
+            timer: 0,
+        }
+    }
+
+    pub fn read(&self) -> u8 {
+        self.register
+    }
+
+    pub fn write(&mut self, value: u8) {
+        self.register = value;
+    }
+
+    /// Whether the channel's DAC is on. A channel with an initial volume of
+    /// 0 which doesn't increase is switched off.
+    pub fn is_dac_enabled(&self) -> bool {
+        self.register & 0xF8 != 0
+    }
+
+    pub fn restart(&mut self) {
+        self.volume = self.register.bits(4..8);
+        self.timer = self.period();
+    }
+
+    pub fn volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn clock(&mut self) {
+        let period = self.period();
+        if period == 0 {
+            return;
+        }
+        if self.timer > 1 {
+            self.timer -= 1;
+            return;
+        }
+        self.timer = period;
+        if self.register.bit(3) {
+            if self.volume < 15 {
+                self.volume += 1;
+            }
+        } else if self.volume > 0 {
+            self.volume -= 1;
+        }
+    }
+
+    fn period(&self) -> u8 {
+        self.register.bits(0..3)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_volume_steps_down_once_per_period() {
+        let mut envelope = Envelope::new();
+        envelope.write(0xF2);
+        envelope.restart();
+        assert_eq!(envelope.volume(), 15);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 15);
+        envelope.clock();
+        assert_eq!(envelope.volume(), 14);
+    }
+
+    #[test]
+    fn the_volume_stops_at_its_limits() {
+        let mut envelope = Envelope::new();
+        envelope.write(0xE9);
+        envelope.restart();
+        for _ in 0..4 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 15);
+
+        envelope.write(0x11);
+        envelope.restart();
+        for _ in 0..4 {
+            envelope.clock();
+        }
+        assert_eq!(envelope.volume(), 0);
+    }
+
+    #[test]
+    fn a_period_of_zero_holds_the_volume() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x70);
+        envelope.restart();
+        envelope.clock();
+        assert_eq!(envelope.volume(), 7);
+    }
+
+    #[test]
+    fn the_dac_is_off_at_zero_volume_decreasing() {
+        let mut envelope = Envelope::new();
+        envelope.write(0x07);
+        assert!(!envelope.is_dac_enabled());
+        envelope.write(0x08);
+        assert!(envelope.is_dac_enabled());
+        envelope.write(0x10);
+        assert!(envelope.is_dac_enabled());
+    }
+}
@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+
+// The FIFO holds 32 samples, and asks for more once half of them are played
+const CAPACITY: usize = 32;
+const REFILL_LEVEL: usize = 16;
+
+/// A Direct Sound FIFO, which queues signed 8 bit samples written by the CPU
+/// or DMA. A sample is taken each time the FIFO's timer overflows and played
+/// until the next is taken.
+pub struct Fifo {
+    samples: VecDeque<i8>,
+    sample: i8,
+}
+
+impl Fifo {
+    pub fn new() -> Fifo {
+        Fifo {
+            samples: VecDeque::with_capacity(CAPACITY),
+            sample: 0,
+        }
+    }
+
+    /// Queue a sample. Samples written to a full FIFO are lost.
+    pub fn write(&mut self, value: u8) {
+        if self.samples.len() < CAPACITY {
+            self.samples.push_back(value as i8);
+        }
+    }
+
+    /// Take the next sample to play, returning whether the FIFO needs
+    /// refilling. The last sample keeps playing if the FIFO is empty.
+    pub fn pop(&mut self) -> bool {
+        if let Some(sample) = self.samples.pop_front() {
+            self.sample = sample;
+        }
+        self.samples.len() <= REFILL_LEVEL
+    }
+
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.sample = 0;
+    }
+
+    /// The sample being played.
+    pub fn sample(&self) -> i8 {
+        self.sample
+    }
+}
@@ -0,0 +1,84 @@
+/// A length counter, which switches a channel off once it has played for a
+/// set time.
+pub struct Length {
+    // The longest length, loaded if the counter has run out when the
+    // channel is restarted
+    max: u16,
+    counter: u16,
+    enabled: bool,
+}
+
+impl Length {
+    pub fn new(max: u16) -> Length {
+        Length {
+            max: max,
+            counter: 0,
+            enabled: false,
+        }
+    }
+
+    /// Load the length register, which counts up towards the maximum.
+    pub fn load(&mut self, value: u16) {
+        self.counter = self.max - value;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn restart(&mut self) {
+        if self.counter == 0 {
+            self.counter = self.max;
+        }
+    }
+
+    /// Count down, returning whether the length has run out.
+    pub fn clock(&mut self) -> bool {
+        if !self.enabled || self.counter == 0 {
+            return false;
+        }
+        self.counter -= 1;
+        self.counter == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_length_runs_out_after_the_loaded_count() {
+        let mut length = Length::new(64);
+        length.set_enabled(true);
+        length.load(62);
+        assert!(!length.clock());
+        assert!(length.clock());
+        assert!(!length.clock());
+    }
+
+    #[test]
+    fn a_disabled_length_never_runs_out() {
+        let mut length = Length::new(64);
+        length.load(63);
+        assert!(!length.clock());
+        length.set_enabled(true);
+        assert!(length.clock());
+    }
+
+    #[test]
+    fn restarting_reloads_an_expired_length() {
+        let mut length = Length::new(256);
+        length.set_enabled(true);
+        length.load(255);
+        assert!(length.clock());
+        length.restart();
+        for _ in 0..255 {
+            assert!(!length.clock());
+        }
+        assert!(length.clock());
+    }
+}
@@ -0,0 +1,380 @@
+use audio::{AudioSink, Resampler};
+use bit::{Bit, Bits, SetBits};
+use bus::{Read, Write};
+use scheduler::{Event, Scheduler};
+use std::cell::RefCell;
+use std::io;
+use std::mem;
+use std::rc::Rc;
+
+mod envelope;
+mod fifo;
+mod length;
+mod noise;
+mod square;
+mod wave;
+
+use self::fifo::Fifo;
+use self::noise::Noise;
+use self::square::Square;
+use self::wave::Wave;
+
+/// The rate at which the APU produces samples, in samples per second.
+pub const SAMPLE_RATE: u32 = 32768;
+
+// The frame sequencer steps at 512 Hz, clocking the length counters, sweep
+// and envelopes, and a sample is mixed every 512 cycles
+const FRAME_SEQUENCER_CYCLES: u64 = 32768;
+const SAMPLE_CYCLES: u64 = 512;
+
+// Frames are passed to the audio sink in blocks of 1024, about 31 ms
+const BLOCK_FRAMES: usize = 1024;
+
+/// The audio processing unit. It owns the sound registers and wave RAM, and
+/// mixes the four Game Boy channels and the two Direct Sound FIFOs into
+/// stereo samples.
+///
+/// Frames are resampled and passed to the audio sink in blocks. Without a
+/// sink they are kept for the frontend to take, up to one second's worth,
+/// after which new frames are dropped.
+pub struct Apu {
+    square1: Square,
+    square2: Square,
+    wave: Wave,
+    noise: Noise,
+    // Direct Sound A and B
+    fifos: [Fifo; 2],
+    soundcnt_l: u16,
+    soundcnt_h: u16,
+    // Sound is switched off by clearing bit 7 of SOUNDCNT_X
+    enabled: bool,
+    soundbias: u16,
+    // The step of the frame sequencer, from 0 to 7
+    frame_step: u8,
+    // Frames mixed since the last block was passed to the sink, or since
+    // they were last taken
+    frames: Vec<[i16; 2]>,
+    sink: Option<(Box<AudioSink>, Resampler)>,
+    scheduler: Rc<RefCell<Scheduler>>,
+}
+
+impl Apu {
+    /// Create an APU, scheduling its frame sequencer and sampling.
+    pub fn new(scheduler: Rc<RefCell<Scheduler>>) -> Apu {
+        {
+            let mut scheduler = scheduler.borrow_mut();
+            scheduler.schedule(FRAME_SEQUENCER_CYCLES, Event::FrameSequencer);
+            scheduler.schedule(SAMPLE_CYCLES, Event::Sample);
+        }
+        Apu {
+            square1: Square::new(),
+            square2: Square::new(),
+            wave: Wave::new(),
+            noise: Noise::new(),
+            fifos: [Fifo::new(), Fifo::new()],
+            soundcnt_l: 0,
+            soundcnt_h: 0,
+            enabled: false,
+            soundbias: 0x200,
+            frame_step: 0,
+            frames: Vec::with_capacity(BLOCK_FRAMES),
+            sink: None,
+            scheduler: scheduler,
+        }
+    }
+
+    /// Send the sound produced from now on to a sink.
+    pub fn set_audio_sink(&mut self, sink: Box<AudioSink>) {
+        let resampler = Resampler::new(SAMPLE_RATE, sink.sample_rate());
+        self.sink = Some((sink, resampler));
+        self.frames.clear();
+    }
+
+    /// Send the frames short of a full block to the audio sink.
+    pub fn flush_audio(&mut self) {
+        if self.sink.is_some() {
+            self.flush();
+        }
+    }
+
+    /// The first error the audio sink has met, if any.
+    pub fn audio_error(&self) -> Option<&io::Error> {
+        self.sink.as_ref().and_then(|(sink, _)| sink.error())
+    }
+
+    /// Send the frames short of a full block to the audio sink, and finish
+    /// its output.
+    pub fn finish_audio(&mut self) -> io::Result<()> {
+        self.flush_audio();
+        match self.sink {
+            Some((ref mut sink, _)) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// The timer which clocks a Direct Sound FIFO, 0 or 1.
+    pub fn fifo_timer(&self, fifo: usize) -> usize {
+        self.soundcnt_h.bit(10 + 4 * fifo as u8) as usize
+    }
+
+    /// Take the next sample from a Direct Sound FIFO as its timer overflows,
+    /// returning whether the FIFO needs refilling by DMA.
+    pub fn pop_fifo(&mut self, fifo: usize) -> bool {
+        self.enabled && self.fifos[fifo].pop()
+    }
+
+    /// Take the frames mixed since they were last taken, at `SAMPLE_RATE`.
+    /// Frames are only kept while there is no audio sink.
+    pub fn take_frames(&mut self) -> Vec<[i16; 2]> {
+        mem::take(&mut self.frames)
+    }
+
+    /// Handle one of the APU's events, which was due at `time`.
+    pub fn handle_event(&mut self, event: Event, time: u64) {
+        match event {
+            Event::FrameSequencer => {
+                self.step_frame_sequencer();
+                self.scheduler.borrow_mut().schedule_at(
+                    time + FRAME_SEQUENCER_CYCLES,
+                    Event::FrameSequencer,
+                );
+            }
+            Event::Sample => {
+                self.sample();
+                self.scheduler
+                    .borrow_mut()
+                    .schedule_at(time + SAMPLE_CYCLES, Event::Sample);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // Lengths are clocked on even steps, the sweep on steps 2 and 6 and the
+    // envelopes on step 7
+    fn step_frame_sequencer(&mut self) {
+        let step = self.frame_step;
+        self.frame_step = (step + 1) % 8;
+        if !self.enabled {
+            return;
+        }
+        if step & 1 == 0 {
+            self.square1.clock_length();
+            self.square2.clock_length();
+            self.wave.clock_length();
+            self.noise.clock_length();
+        }
+        if step == 2 || step == 6 {
+            self.square1.clock_sweep();
+        }
+        if step == 7 {
+            self.square1.clock_envelope();
+            self.square2.clock_envelope();
+            self.noise.clock_envelope();
+        }
+    }
+
+    fn sample(&mut self) {
+        let cycles = SAMPLE_CYCLES as u32;
+        self.square1.step(cycles);
+        self.square2.step(cycles);
+        self.wave.step(cycles);
+        self.noise.step(cycles);
+
+        if self.sink.is_none() && self.frames.len() >= SAMPLE_RATE as usize {
+            return;
+        }
+        let frame = if self.enabled { self.mix() } else { [0; 2] };
+        self.frames.push(frame);
+        if self.sink.is_some() && self.frames.len() == BLOCK_FRAMES {
+            self.flush();
+        }
+    }
+
+    fn flush(&mut self) {
+        if let Some((ref mut sink, ref mut resampler)) = self.sink {
+            let mut output = Vec::with_capacity(2 * BLOCK_FRAMES);
+            resampler.resample(&self.frames, &mut output);
+            sink.push(&output);
+        }
+        self.frames.clear();
+    }
+
+    // Mix the channels enabled on each side. The PSG channels are scaled by
+    // each side's master volume and the PSG volume in SOUNDCNT_H, and the
+    // FIFOs by their own volumes. SOUNDBIAS is added and the result clipped
+    // to the 10 bit range of the DAC, at the resolution SOUNDBIAS selects,
+    // then centred and scaled up to 16 bits.
+    fn mix(&self) -> [i16; 2] {
+        let outputs = [
+            self.square1.output(),
+            self.square2.output(),
+            self.wave.output(),
+            self.noise.output(),
+        ];
+        let control = self.soundcnt_h;
+        let side = |enables: u16, volume: u16, fifo_enables: [bool; 2]| {
+            let sum: i16 = outputs
+                .iter()
+                .enumerate()
+                .filter(|&(channel, _)| enables.bit(channel as u8))
+                .map(|(_, output)| *output)
+                .sum();
+            let psg = sum * (volume as i16 + 1);
+            let mut sample = match control.bits(0..2) {
+                0 => psg >> 2,
+                1 => psg >> 1,
+                _ => psg,
+            };
+            for (fifo, &enabled) in fifo_enables.iter().enumerate() {
+                if enabled {
+                    // The FIFOs play at 50% or 100%
+                    let shift = 1 + control.bit(2 + fifo as u8) as u8;
+                    sample += (self.fifos[fifo].sample() as i16) << shift;
+                }
+            }
+
+            let level = self.soundbias.bits(0..10) as i16;
+            let resolution = self.soundbias.bits(14..16) as u8;
+            let sample = (sample + level).clamp(0, 0x3FF);
+            let sample = sample & !((1 << (resolution + 1)) - 1);
+            (sample - 0x200) * 64
+        };
+
+        let psg = self.soundcnt_l;
+        let left = side(
+            psg.bits(12..16),
+            psg.bits(4..7),
+            [control.bit(9), control.bit(13)],
+        );
+        let right = side(
+            psg.bits(8..12),
+            psg.bits(0..3),
+            [control.bit(8), control.bit(12)],
+        );
+        [left, right]
+    }
+
+    // Switching sound off resets the PSG registers, which can't be written
+    // until it is switched back on
+    fn write_soundcnt_x(&mut self, value: u8) {
+        self.enabled = value.bit(7);
+        if !self.enabled {
+            self.square1 = Square::new();
+            self.square2 = Square::new();
+            self.wave.reset();
+            self.noise = Noise::new();
+            self.soundcnt_l = 0;
+        }
+    }
+
+    // The FIFO reset bits are write-only
+    fn write_soundcnt_h(&mut self, value: u8) {
+        self.soundcnt_h.set_bits(8..16, value as u16 & 0x77);
+        for fifo in 0..2 {
+            if value.bit(3 + 4 * fifo as u8) {
+                self.fifos[fifo].reset();
+            }
+        }
+    }
+
+    fn soundcnt_x(&self) -> u8 {
+        (self.enabled as u8) << 7
+            | (self.noise.is_enabled() as u8) << 3
+            | (self.wave.is_enabled() as u8) << 2
+            | (self.square2.is_enabled() as u8) << 1
+            | self.square1.is_enabled() as u8
+    }
+}
+
+// The registers of the four channels are laid out as on the Game Boy, so
+// each is a byte. Frequencies, lengths and the FIFOs are write-only, and
+// unused bytes read as 0.
+impl Read for Apu {
+    fn read_byte(&self, address: u32) -> u8 {
+        match address {
+            0x60 => self.square1.read_sweep(),
+            0x62 => self.square1.read_duty_length(),
+            0x63 => self.square1.read_envelope(),
+            0x65 => self.square1.read_control(),
+            0x68 => self.square2.read_duty_length(),
+            0x69 => self.square2.read_envelope(),
+            0x6D => self.square2.read_control(),
+            0x70 => self.wave.read_select(),
+            0x73 => self.wave.read_volume(),
+            0x75 => self.wave.read_control(),
+            0x79 => self.noise.read_envelope(),
+            0x7C => self.noise.read_polynomial(),
+            0x7D => self.noise.read_control(),
+            0x80 => self.soundcnt_l as u8,
+            0x81 => (self.soundcnt_l >> 8) as u8,
+            0x82 => self.soundcnt_h as u8,
+            0x83 => (self.soundcnt_h >> 8) as u8,
+            0x84 => self.soundcnt_x(),
+            0x88 => self.soundbias as u8,
+            0x89 => (self.soundbias >> 8) as u8,
+            0x90...0x9F => self.wave.read_ram(address as usize - 0x90),
+            _ => 0,
+        }
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        self.read_byte(address) as u16
+            | (self.read_byte(address + 1) as u16) << 8
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        self.read_halfword(address) as u32
+            | (self.read_halfword(address + 2) as u32) << 16
+    }
+}
+
+impl Write for Apu {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        // Only wave RAM and the master control can be written with sound
+        // switched off
+        if !self.enabled && address < 0x82 {
+            return;
+        }
+        match address {
+            0x60 => self.square1.write_sweep(value),
+            0x62 => self.square1.write_duty_length(value),
+            0x63 => self.square1.write_envelope(value),
+            0x64 => self.square1.write_frequency_low(value),
+            0x65 => self.square1.write_control(value),
+            0x68 => self.square2.write_duty_length(value),
+            0x69 => self.square2.write_envelope(value),
+            0x6C => self.square2.write_frequency_low(value),
+            0x6D => self.square2.write_control(value),
+            0x70 => self.wave.write_select(value),
+            0x72 => self.wave.write_length(value),
+            0x73 => self.wave.write_volume(value),
+            0x74 => self.wave.write_frequency_low(value),
+            0x75 => self.wave.write_control(value),
+            0x78 => self.noise.write_length(value),
+            0x79 => self.noise.write_envelope(value),
+            0x7C => self.noise.write_polynomial(value),
+            0x7D => self.noise.write_control(value),
+            0x80 => self.soundcnt_l.set_bits(0..8, value as u16 & 0x77),
+            0x81 => self.soundcnt_l.set_bits(8..16, value as u16),
+            0x82 => self.soundcnt_h.set_bits(0..8, value as u16 & 0x0F),
+            0x83 => self.write_soundcnt_h(value),
+            0x84 => self.write_soundcnt_x(value),
+            0x88 => self.soundbias.set_bits(0..8, value as u16 & 0xFE),
+            0x89 => self.soundbias.set_bits(8..16, value as u16 & 0xC3),
+            0x90...0x9F => self.wave.write_ram(address as usize - 0x90, value),
+            0xA0...0xA3 => self.fifos[0].write(value),
+            0xA4...0xA7 => self.fifos[1].write(value),
+            _ => {}
+        }
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        self.write_byte(address, value as u8);
+        self.write_byte(address + 1, (value >> 8) as u8);
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        self.write_halfword(address, value as u16);
+        self.write_halfword(address + 2, (value >> 16) as u16);
+    }
+}
@@ -0,0 +1,135 @@
+use apu::envelope::Envelope;
+use apu::length::Length;
+use bit::{Bit, Bits, SetBit};
+
+/// The noise channel, which plays the output of a linear feedback shift
+/// register.
+pub struct Noise {
+    length: Length,
+    envelope: Envelope,
+    // The polynomial register: the divider in bits 0-2, a 7 bit rather than
+    // 15 bit register in bit 3 and the shift in bits 4-7
+    polynomial: u8,
+    enabled: bool,
+    lfsr: u16,
+    // Cycles since the register was last shifted
+    elapsed: u32,
+}
+
+impl Noise {
+    pub fn new() -> Noise {
+        Noise {
+            length: Length::new(64),
+            envelope: Envelope::new(),
+            polynomial: 0,
+            enabled: false,
+            lfsr: 0x7FFF,
+            elapsed: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // The length is write-only
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value.bits(0..6) as u16);
+    }
+
+    pub fn read_envelope(&self) -> u8 {
+        self.envelope.read()
+    }
+
+    pub fn write_envelope(&mut self, value: u8) {
+        self.envelope.write(value);
+        if !self.envelope.is_dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn read_polynomial(&self) -> u8 {
+        self.polynomial
+    }
+
+    pub fn write_polynomial(&mut self, value: u8) {
+        self.polynomial = value;
+    }
+
+    // Only the length enable flag can be read
+    pub fn read_control(&self) -> u8 {
+        (self.length.is_enabled() as u8) << 6
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.length.set_enabled(value.bit(6));
+        if value.bit(7) {
+            self.enabled = self.envelope.is_dac_enabled();
+            self.length.restart();
+            self.envelope.restart();
+            self.lfsr = if self.polynomial.bit(3) { 0x7F } else { 0x7FFF };
+            self.elapsed = 0;
+        }
+    }
+
+    // The cycles between shifts of the register, which isn't clocked at all
+    // with a shift of 14 or 15
+    fn period(&self) -> Option<u32> {
+        let shift = self.polynomial.bits(4..8) as u32;
+        if shift >= 14 {
+            return None;
+        }
+        let divider = match self.polynomial.bits(0..3) as u32 {
+            0 => 32,
+            divider => divider * 64,
+        };
+        Some(divider << shift)
+    }
+
+    /// Shift the register for each period in a number of cycles.
+    pub fn step(&mut self, cycles: u32) {
+        let period = match self.period() {
+            Some(period) if self.enabled => period,
+            _ => return,
+        };
+        self.elapsed += cycles;
+        while self.elapsed >= period {
+            self.elapsed -= period;
+            self.shift();
+        }
+    }
+
+    // Shift the register right, feeding back the XOR of the two lowest bits
+    // into the top bit
+    fn shift(&mut self) {
+        let feedback = self.lfsr.bit(0) != self.lfsr.bit(1);
+        self.lfsr >>= 1;
+        self.lfsr.set_bit(14, feedback);
+        if self.polynomial.bit(3) {
+            self.lfsr.set_bit(6, feedback);
+        }
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    /// The channel's output, from -15 to 15.
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let volume = self.envelope.volume() as i16;
+        if self.lfsr.bit(0) {
+            -volume
+        } else {
+            volume
+        }
+    }
+}
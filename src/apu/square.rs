@@ -0,0 +1,206 @@
+use apu::envelope::Envelope;
+use apu::length::Length;
+use bit::{Bit, Bits};
+
+// The waveforms for each duty cycle, one bit per step, of 12.5%, 25%, 50%
+// and 75% high
+const DUTY_PATTERNS: [u8; 4] =
+    [0b0000_0001, 0b1000_0001, 0b1000_0111, 0b0111_1110];
+
+/// A square wave channel, with a duty cycle, envelope and length, and for
+/// channel 1 a frequency sweep.
+pub struct Square {
+    sweep: Sweep,
+    duty: u8,
+    length: Length,
+    envelope: Envelope,
+    frequency: u16,
+    enabled: bool,
+    // The step of the waveform being played, and the cycles spent on it
+    position: u8,
+    elapsed: u32,
+}
+
+/// The frequency sweep of channel 1, which periodically shifts the
+/// frequency up or down.
+struct Sweep {
+    // The sweep register: the shift in bits 0-2, the direction in bit 3
+    // (set to decrease) and the step time in bits 4-6
+    register: u8,
+    shadow: u16,
+    timer: u8,
+    enabled: bool,
+}
+
+impl Sweep {
+    fn period(&self) -> u8 {
+        self.register.bits(4..7)
+    }
+
+    fn shift(&self) -> u8 {
+        self.register.bits(0..3)
+    }
+
+    // The frequency after the next sweep, which may be beyond the highest
+    // frequency of 2047
+    fn next_frequency(&self) -> u16 {
+        let delta = self.shadow >> self.shift();
+        if self.register.bit(3) {
+            self.shadow - delta
+        } else {
+            self.shadow + delta
+        }
+    }
+}
+
+impl Square {
+    pub fn new() -> Square {
+        Square {
+            sweep: Sweep {
+                register: 0,
+                shadow: 0,
+                timer: 0,
+                enabled: false,
+            },
+            duty: 0,
+            length: Length::new(64),
+            envelope: Envelope::new(),
+            frequency: 0,
+            enabled: false,
+            position: 0,
+            elapsed: 0,
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    // Registers, which are the same as the Game Boy's NRx0 to NRx4
+
+    pub fn read_sweep(&self) -> u8 {
+        self.sweep.register
+    }
+
+    pub fn write_sweep(&mut self, value: u8) {
+        self.sweep.register = value & 0x7F;
+    }
+
+    // The length is write-only
+    pub fn read_duty_length(&self) -> u8 {
+        self.duty << 6
+    }
+
+    pub fn write_duty_length(&mut self, value: u8) {
+        self.duty = value.bits(6..8);
+        self.length.load(value.bits(0..6) as u16);
+    }
+
+    pub fn read_envelope(&self) -> u8 {
+        self.envelope.read()
+    }
+
+    pub fn write_envelope(&mut self, value: u8) {
+        self.envelope.write(value);
+        if !self.envelope.is_dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = self.frequency & 0x700 | value as u16;
+    }
+
+    // Only the length enable flag can be read
+    pub fn read_control(&self) -> u8 {
+        (self.length.is_enabled() as u8) << 6
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.frequency =
+            self.frequency & 0xFF | (value.bits(0..3) as u16) << 8;
+        self.length.set_enabled(value.bit(6));
+        if value.bit(7) {
+            self.restart();
+        }
+    }
+
+    fn restart(&mut self) {
+        self.enabled = self.envelope.is_dac_enabled();
+        self.length.restart();
+        self.envelope.restart();
+        self.elapsed = 0;
+
+        self.sweep.shadow = self.frequency;
+        self.sweep.timer = self.sweep_period();
+        self.sweep.enabled =
+            self.sweep.period() != 0 || self.sweep.shift() != 0;
+        if self.sweep.shift() != 0 && self.sweep.next_frequency() > 2047 {
+            self.enabled = false;
+        }
+    }
+
+    // A sweep time of 0 is treated as 8
+    fn sweep_period(&self) -> u8 {
+        match self.sweep.period() {
+            0 => 8,
+            period => period,
+        }
+    }
+
+    /// Advance the waveform by a number of cycles. Each step of the
+    /// waveform lasts 16 cycles for each unit the frequency is below 2048.
+    pub fn step(&mut self, cycles: u32) {
+        let period = (2048 - self.frequency as u32) * 16;
+        self.elapsed += cycles;
+        let steps = self.elapsed / period;
+        self.elapsed %= period;
+        self.position = ((self.position as u32 + steps) % 8) as u8;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn clock_envelope(&mut self) {
+        self.envelope.clock();
+    }
+
+    pub fn clock_sweep(&mut self) {
+        if self.sweep.timer > 1 {
+            self.sweep.timer -= 1;
+            return;
+        }
+        self.sweep.timer = self.sweep_period();
+        if !self.sweep.enabled || self.sweep.period() == 0 {
+            return;
+        }
+
+        let frequency = self.sweep.next_frequency();
+        if frequency > 2047 {
+            self.enabled = false;
+        } else if self.sweep.shift() != 0 {
+            self.sweep.shadow = frequency;
+            self.frequency = frequency;
+            // The next frequency is checked for overflow straight away
+            if self.sweep.next_frequency() > 2047 {
+                self.enabled = false;
+            }
+        }
+    }
+
+    /// The channel's output, from -15 to 15.
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let volume = self.envelope.volume() as i16;
+        if DUTY_PATTERNS[self.duty as usize].bit(self.position) {
+            volume
+        } else {
+            -volume
+        }
+    }
+}
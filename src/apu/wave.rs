@@ -0,0 +1,157 @@
+use apu::length::Length;
+use bit::{Bit, Bits};
+
+/// The wave channel, which plays 4 bit samples from wave RAM.
+///
+/// Wave RAM holds two banks of 32 samples. One bank is played while the
+/// other is accessed through WAVE_RAM, or both are played in turn as 64
+/// samples.
+pub struct Wave {
+    // The select register: two banks in bit 5, the bank played in bit 6 and
+    // the DAC enable in bit 7
+    select: u8,
+    length: Length,
+    // The volume register: the volume in bits 5-6 and 75% volume in bit 7
+    volume: u8,
+    frequency: u16,
+    enabled: bool,
+    banks: [[u8; 16]; 2],
+    // The sample being played, counted from the start of the first bank
+    // played, and the cycles spent on it
+    position: u8,
+    elapsed: u32,
+}
+
+impl Wave {
+    pub fn new() -> Wave {
+        Wave {
+            select: 0,
+            length: Length::new(256),
+            volume: 0,
+            frequency: 0,
+            enabled: false,
+            banks: [[0; 16]; 2],
+            position: 0,
+            elapsed: 0,
+        }
+    }
+
+    /// Reset the registers, keeping the contents of wave RAM.
+    pub fn reset(&mut self) {
+        let banks = self.banks;
+        *self = Wave::new();
+        self.banks = banks;
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    fn is_dac_enabled(&self) -> bool {
+        self.select.bit(7)
+    }
+
+    fn bank(&self) -> usize {
+        self.select.bit(6) as usize
+    }
+
+    pub fn read_select(&self) -> u8 {
+        self.select
+    }
+
+    pub fn write_select(&mut self, value: u8) {
+        self.select = value & 0xE0;
+        if !self.is_dac_enabled() {
+            self.enabled = false;
+        }
+    }
+
+    pub fn write_length(&mut self, value: u8) {
+        self.length.load(value as u16);
+    }
+
+    pub fn read_volume(&self) -> u8 {
+        self.volume
+    }
+
+    pub fn write_volume(&mut self, value: u8) {
+        self.volume = value & 0xE0;
+    }
+
+    pub fn write_frequency_low(&mut self, value: u8) {
+        self.frequency = self.frequency & 0x700 | value as u16;
+    }
+
+    // Only the length enable flag can be read
+    pub fn read_control(&self) -> u8 {
+        (self.length.is_enabled() as u8) << 6
+    }
+
+    pub fn write_control(&mut self, value: u8) {
+        self.frequency =
+            self.frequency & 0xFF | (value.bits(0..3) as u16) << 8;
+        self.length.set_enabled(value.bit(6));
+        if value.bit(7) {
+            self.enabled = self.is_dac_enabled();
+            self.length.restart();
+            self.position = 0;
+            self.elapsed = 0;
+        }
+    }
+
+    /// Read a byte of the bank which isn't being played.
+    pub fn read_ram(&self, offset: usize) -> u8 {
+        self.banks[self.bank() ^ 1][offset]
+    }
+
+    pub fn write_ram(&mut self, offset: usize, value: u8) {
+        self.banks[self.bank() ^ 1][offset] = value;
+    }
+
+    /// Advance through the samples by a number of cycles. Each sample lasts
+    /// 8 cycles for each unit the frequency is below 2048.
+    pub fn step(&mut self, cycles: u32) {
+        if !self.enabled {
+            return;
+        }
+        let period = (2048 - self.frequency as u32) * 8;
+        let samples = if self.select.bit(5) { 64 } else { 32 };
+        self.elapsed += cycles;
+        let steps = self.elapsed / period;
+        self.elapsed %= period;
+        self.position = ((self.position as u32 + steps) % samples) as u8;
+    }
+
+    pub fn clock_length(&mut self) {
+        if self.length.clock() {
+            self.enabled = false;
+        }
+    }
+
+    /// The channel's output, from -15 to 15.
+    pub fn output(&self) -> i16 {
+        if !self.enabled {
+            return 0;
+        }
+        let bank = (self.bank() + self.position as usize / 32) % 2;
+        let byte = self.banks[bank][(self.position as usize % 32) / 2];
+        // The high nibble of each byte is played first
+        let sample = if self.position.bit(0) {
+            byte.bits(0..4)
+        } else {
+            byte.bits(4..8)
+        };
+        let sample = sample as i16 * 2 - 15;
+
+        if self.volume.bit(7) {
+            sample * 3 / 4
+        } else {
+            match self.volume.bits(5..7) {
+                0 => 0,
+                1 => sample,
+                2 => sample / 2,
+                _ => sample / 4,
+            }
+        }
+    }
+}
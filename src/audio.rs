@@ -0,0 +1,128 @@
+use std::io;
+
+/// A destination for the sound the emulator produces, such as an audio
+/// device or a file.
+pub trait AudioSink {
+    /// The rate the sink plays at in frames per second, which the APU's
+    /// output is resampled to.
+    fn sample_rate(&self) -> u32;
+
+    /// Take a block of frames, each a left and a right sample.
+    fn push(&mut self, frames: &[[i16; 2]]);
+
+    /// The first error the sink has met, if any, which `finish` will
+    /// return.
+    fn error(&self) -> Option<&io::Error> {
+        None
+    }
+
+    /// Finish the output once no more frames will be pushed, returning the
+    /// first error the sink met.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Converts a stream of frames from one sample rate to another, linearly
+/// interpolating between input frames.
+pub struct Resampler {
+    from: u32,
+    to: u32,
+    // The position of the next output frame past the previous input frame,
+    // in units of 1/`to` of an input frame
+    phase: u32,
+    previous: [i16; 2],
+}
+
+impl Resampler {
+    pub fn new(from: u32, to: u32) -> Resampler {
+        Resampler {
+            from: from,
+            to: to,
+            phase: 0,
+            previous: [0; 2],
+        }
+    }
+
+    /// Resample a block of frames, appending the output to `output`.
+    pub fn resample(
+        &mut self,
+        input: &[[i16; 2]],
+        output: &mut Vec<[i16; 2]>,
+    ) {
+        for frame in input {
+            while self.phase < self.to {
+                let mut interpolated = [0; 2];
+                for side in 0..2 {
+                    let start = self.previous[side] as i64;
+                    let delta = frame[side] as i64 - start;
+                    let offset = delta * self.phase as i64 / self.to as i64;
+                    interpolated[side] = (start + offset) as i16;
+                }
+                output.push(interpolated);
+                self.phase += self.from;
+            }
+            self.phase -= self.to;
+            self.previous = *frame;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resample(
+        resampler: &mut Resampler,
+        input: &[[i16; 2]],
+    ) -> Vec<[i16; 2]> {
+        let mut output = Vec::new();
+        resampler.resample(input, &mut output);
+        output
+    }
+
+    #[test]
+    fn equal_rates_pass_frames_through_one_frame_late() {
+        let mut resampler = Resampler::new(100, 100);
+        let output = resample(&mut resampler, &[[1, -1], [2, -2], [3, -3]]);
+        assert_eq!(output, vec![[0, 0], [1, -1], [2, -2]]);
+    }
+
+    #[test]
+    fn doubling_the_rate_interpolates_midpoints() {
+        let mut resampler = Resampler::new(1, 2);
+        let output = resample(&mut resampler, &[[100, -100], [200, -200]]);
+        assert_eq!(output, vec![[0, 0], [50, -50], [100, -100], [150, -150]]);
+    }
+
+    #[test]
+    fn halving_the_rate_drops_every_other_frame() {
+        let mut resampler = Resampler::new(2, 1);
+        let input = [[10, 10], [20, 20], [30, 30], [40, 40]];
+        let output = resample(&mut resampler, &input);
+        assert_eq!(output, vec![[0, 0], [20, 20]]);
+    }
+
+    #[test]
+    fn a_second_of_input_gives_a_second_of_output() {
+        let mut resampler = Resampler::new(32768, 48000);
+        let input = vec![[0; 2]; 32768];
+        assert_eq!(resample(&mut resampler, &input).len(), 48000);
+    }
+
+    #[test]
+    fn blocks_continue_where_the_last_one_stopped() {
+        let input: Vec<[i16; 2]> =
+            (0..100).map(|i| [i * 7, -i * 3]).collect();
+
+        let mut whole = Resampler::new(32768, 44100);
+        let expected = resample(&mut whole, &input);
+
+        let mut split = Resampler::new(32768, 44100);
+        let mut output = Vec::new();
+        for block in input.chunks(13) {
+            split.resample(block, &mut output);
+        }
+        assert_eq!(output, expected);
+    }
+}
@@ -0,0 +1,530 @@
+use bit::{Bit, Bits};
+use bus::{Read, Write};
+use byteorder::{ByteOrder, LittleEndian};
+use cpu::{Cpu, Exception, ProgramStatusRegister, Register, LR, PC, SP};
+use memory_map::PowerMode;
+use std::f64::consts::PI;
+
+const R0: Register = Register(0);
+const R1: Register = Register(1);
+const R2: Register = Register(2);
+const R3: Register = Register(3);
+
+// Interrupt flags acknowledged by the game's interrupt handler, which
+// IntrWait waits on
+const BIOS_IF: u32 = 0x3007FF8;
+// Selects whether SoftReset enters the game at ROM or EWRAM
+const RETURN_ADDRESS_SEL: u32 = 0x3007FFA;
+
+const IE: u32 = 0x4000200;
+const IF: u32 = 0x4000202;
+const IME: u32 = 0x4000208;
+
+/// State of the emulated BIOS that must be kept between calls.
+pub struct Hle {
+    // Whether the real BIOS is loaded, so that the functions which aren't
+    // emulated can be left to it
+    real_bios: bool,
+    // Flags an IntrWait call is waiting on. The SWI is executed repeatedly
+    // until one of them is acknowledged.
+    intr_wait: Option<u16>,
+    // Functions called without a real BIOS to run them, in the order they
+    // were first called
+    unsupported: Vec<u32>,
+}
+
+impl Hle {
+    pub fn new(real_bios: bool) -> Hle {
+        Hle {
+            real_bios: real_bios,
+            intr_wait: None,
+            unsupported: Vec::new(),
+        }
+    }
+
+    /// The functions which aren't emulated that have been called without a
+    /// real BIOS to run them, each listed once in the order first called.
+    pub fn unsupported(&self) -> &[u32] {
+        &self.unsupported
+    }
+}
+
+/// Build a stand-in for the BIOS ROM for use without the real BIOS. It only
+/// contains the IRQ handler, which calls the game's handler through the
+/// pointer at 0x3FFFFFC. The other vectors loop forever.
+pub fn image() -> Vec<u8> {
+    let mut data = vec![0; 0x4000];
+    for &vector in &[0x00, 0x04, 0x08, 0x0C, 0x10, 0x14] {
+        // b .
+        LittleEndian::write_u32(&mut data[vector..], 0xEAFFFFFE);
+    }
+    let irq_handler = [
+        0xE92D500F, // stmfd sp!, {r0-r3, r12, lr}
+        0xE3A00301, // mov r0, #0x4000000
+        0xE28FE000, // add lr, pc, #0
+        0xE510F004, // ldr pc, [r0, #-4]
+        0xE8BD500F, // ldmfd sp!, {r0-r3, r12, lr}
+        0xE25EF004, // subs pc, lr, #4
+    ];
+    for (i, &inst) in irq_handler.iter().enumerate() {
+        LittleEndian::write_u32(&mut data[0x18 + i * 4..], inst);
+    }
+    data
+}
+
+/// Put the CPU in the state the BIOS leaves it in before entering the game
+/// at `entry`: System mode, ARM state, with each mode's stack set up.
+pub fn boot(cpu: &mut Cpu, entry: u32) {
+    let stacks = [(0x13, 0x3007FE0), (0x12, 0x3007FA0), (0x1F, 0x3007F00)];
+    for &(mode, sp) in &stacks {
+        cpu.set_cpsr(ProgramStatusRegister::from_bits(mode));
+        cpu.set_spsr(ProgramStatusRegister::from_bits(0));
+        cpu.registers[SP] = sp;
+        cpu.registers[LR] = 0;
+    }
+    for i in 0..13 {
+        cpu.registers[Register(i)] = 0;
+    }
+    cpu.registers[PC] = entry;
+}
+
+/// Service a BIOS call in place of the SWI exception. Arguments and results
+/// are passed in r0-r3 as with the real BIOS.
+pub fn call(cpu: &mut Cpu, function: u32) {
+    match function {
+        0x00 => soft_reset(cpu),
+        0x01 => register_ram_reset(cpu),
+        0x02 => cpu.memory.set_power_mode(PowerMode::Halted),
+        0x03 => cpu.memory.set_power_mode(PowerMode::Stopped),
+        0x04 => intr_wait(cpu),
+        0x05 => {
+            cpu.registers[R0] = 1;
+            cpu.registers[R1] = 1;
+            intr_wait(cpu);
+        }
+        0x06 => {
+            let (number, denom) = (cpu.registers[R0], cpu.registers[R1]);
+            div(cpu, number, denom);
+        }
+        0x07 => {
+            let (number, denom) = (cpu.registers[R1], cpu.registers[R0]);
+            div(cpu, number, denom);
+        }
+        0x08 => {
+            let value = cpu.registers[R0];
+            cpu.registers[R0] = (value as f64).sqrt() as u32;
+        }
+        0x0A => arctan2(cpu),
+        0x0B => cpu_set(cpu),
+        0x0C => cpu_fast_set(cpu),
+        0x0E => bg_affine_set(cpu),
+        0x0F => obj_affine_set(cpu),
+        0x11 | 0x12 => {
+            let data = lz77_decompress(cpu, cpu.registers[R0]);
+            write_decompressed(cpu, &data, function == 0x12);
+        }
+        0x13 => {
+            let data = huffman_decompress(cpu, cpu.registers[R0]);
+            write_decompressed(cpu, &data, true);
+        }
+        0x14 | 0x15 => {
+            let data = rl_decompress(cpu, cpu.registers[R0]);
+            write_decompressed(cpu, &data, function == 0x15);
+        }
+        _ => unsupported(cpu, function),
+    }
+}
+
+// Hand a function which isn't emulated to the real BIOS, or without one
+// record it and return without doing anything
+fn unsupported(cpu: &mut Cpu, function: u32) {
+    let real_bios = cpu.hle.as_ref().map(|hle| hle.real_bios) == Some(true);
+    if real_bios {
+        cpu.exception(Exception::SoftwareInterrupt);
+    } else if let Some(ref mut hle) = cpu.hle {
+        if !hle.unsupported.contains(&function) {
+            hle.unsupported.push(function);
+        }
+    }
+}
+
+fn soft_reset(cpu: &mut Cpu) {
+    let entry = if cpu.memory.read_byte(RETURN_ADDRESS_SEL) == 0 {
+        0x8000000
+    } else {
+        0x2000000
+    };
+    fill(cpu, 0x3007E00, 0x200);
+    boot(cpu, entry);
+}
+
+fn register_ram_reset(cpu: &mut Cpu) {
+    let flags = cpu.registers[R0];
+    // The top of IWRAM holds the stacks and BIOS variables so is kept
+    let regions = [
+        (0x2000000, 0x40000),
+        (0x3000000, 0x7E00),
+        (0x5000000, 0x400),
+        (0x6000000, 0x18000),
+        (0x7000000, 0x400),
+    ];
+    for (i, &(address, length)) in regions.iter().enumerate() {
+        if flags.bit(i as u8) {
+            fill(cpu, address, length);
+        }
+    }
+    if flags.bit(5) {
+        fill(cpu, 0x4000120, 0x10);
+    }
+    if flags.bit(6) {
+        fill(cpu, 0x4000060, 0x48);
+    }
+    if flags.bit(7) {
+        fill(cpu, 0x4000000, 0x58);
+        fill(cpu, 0x40000B0, 0x30);
+        fill(cpu, 0x4000100, 0x10);
+        fill(cpu, IE, 0x4);
+        fill(cpu, IME, 0x2);
+    }
+}
+
+fn fill(cpu: &mut Cpu, address: u32, length: u32) {
+    for offset in (0..length).step_by(2) {
+        cpu.memory.write_halfword(address + offset, 0);
+    }
+}
+
+/// Wait for one of the interrupts in r1 to be acknowledged in BIOS_IF,
+/// discarding any already acknowledged first if r0 is set.
+///
+/// The CPU is halted and the SWI rewound so that it is executed again once
+/// an interrupt has been handled.
+fn intr_wait(cpu: &mut Cpu) {
+    cpu.memory.write_halfword(IME, 1);
+
+    let flags = match cpu.hle.as_ref().and_then(|hle| hle.intr_wait) {
+        Some(flags) => flags,
+        None => {
+            let flags = cpu.registers[R1] as u16;
+            if cpu.registers[R0] != 0 {
+                let bios_if = cpu.memory.read_halfword(BIOS_IF);
+                cpu.memory.write_halfword(BIOS_IF, bios_if & !flags);
+            }
+            flags
+        }
+    };
+
+    let bios_if = cpu.memory.read_halfword(BIOS_IF);
+    if bios_if & flags != 0 {
+        cpu.memory.write_halfword(BIOS_IF, bios_if & !flags);
+        set_intr_wait(cpu, None);
+        return;
+    }
+
+    set_intr_wait(cpu, Some(flags));
+    // An interrupt already pending would wake the CPU straight away, and
+    // must be taken before the SWI is executed again
+    let pending = cpu.memory.read_halfword(IE) & cpu.memory.read_halfword(IF);
+    if pending == 0 {
+        cpu.memory.set_power_mode(PowerMode::Halted);
+    }
+    let size = if cpu.cpsr.t() { 2 } else { 4 };
+    cpu.registers[PC] -= 2 * size;
+}
+
+fn set_intr_wait(cpu: &mut Cpu, flags: Option<u16>) {
+    if let Some(ref mut hle) = cpu.hle {
+        hle.intr_wait = flags;
+    }
+}
+
+fn div(cpu: &mut Cpu, number: u32, denom: u32) {
+    let (number, denom) = (number as i32, denom as i32);
+    // The BIOS never returns from most divisions by zero, so return what it
+    // does when the numerator is -1, 0 or 1
+    if denom == 0 {
+        cpu.registers[R0] = if number < 0 { -1i32 as u32 } else { 1 };
+        cpu.registers[R1] = number as u32;
+        cpu.registers[R3] = 1;
+        return;
+    }
+    let quotient = number.wrapping_div(denom);
+    cpu.registers[R0] = quotient as u32;
+    cpu.registers[R1] = number.wrapping_rem(denom) as u32;
+    cpu.registers[R3] = quotient.wrapping_abs() as u32;
+}
+
+fn arctan2(cpu: &mut Cpu) {
+    let x = cpu.registers[R0] as i16 as f64;
+    let y = cpu.registers[R1] as i16 as f64;
+    let mut angle = y.atan2(x);
+    if angle < 0.0 {
+        angle += 2.0 * PI;
+    }
+    // A full turn is 0x10000
+    cpu.registers[R0] = (angle / (2.0 * PI) * 65536.0) as u32 & 0xFFFF;
+}
+
+fn cpu_set(cpu: &mut Cpu) {
+    let (mut src, mut dst) = (cpu.registers[R0], cpu.registers[R1]);
+    let control = cpu.registers[R2];
+    let count = control.bits(0..21);
+    let fixed = control.bit(24);
+
+    if control.bit(26) {
+        src &= !3;
+        dst &= !3;
+        for _ in 0..count {
+            let value = cpu.memory.read_word(src);
+            cpu.memory.write_word(dst, value);
+            if !fixed {
+                src += 4;
+            }
+            dst += 4;
+        }
+    } else {
+        src &= !1;
+        dst &= !1;
+        for _ in 0..count {
+            let value = cpu.memory.read_halfword(src);
+            cpu.memory.write_halfword(dst, value);
+            if !fixed {
+                src += 2;
+            }
+            dst += 2;
+        }
+    }
+}
+
+fn cpu_fast_set(cpu: &mut Cpu) {
+    let src = cpu.registers[R0] & !3;
+    let mut dst = cpu.registers[R1] & !3;
+    let control = cpu.registers[R2];
+    // Words are transferred in blocks of eight
+    let count = (control.bits(0..21) + 7) & !7;
+    let fixed = control.bit(24);
+
+    for i in 0..count {
+        let address = if fixed { src } else { src + i * 4 };
+        let value = cpu.memory.read_word(address);
+        cpu.memory.write_word(dst, value);
+        dst += 4;
+    }
+}
+
+/// Calculate the parameters of a scaled and rotated affine transform, in
+/// 8.8 fixed point. The angle is a fraction of a full turn, of which only
+/// the top 8 bits are used.
+fn affine(scale_x: u16, scale_y: u16, angle: u16) -> (f64, f64, f64, f64) {
+    let scale_x = scale_x as i16 as f64 / 256.0;
+    let scale_y = scale_y as i16 as f64 / 256.0;
+    let theta = (angle >> 8) as f64 / 128.0 * PI;
+    let (sin, cos) = theta.sin_cos();
+    (cos * scale_x, -sin * scale_x, sin * scale_y, cos * scale_y)
+}
+
+fn bg_affine_set(cpu: &mut Cpu) {
+    let (mut src, mut dst) = (cpu.registers[R0], cpu.registers[R1]);
+    for _ in 0..cpu.registers[R2] {
+        let origin_x = cpu.memory.read_word(src) as i32 as f64 / 256.0;
+        let origin_y = cpu.memory.read_word(src + 4) as i32 as f64 / 256.0;
+        let center_x = cpu.memory.read_halfword(src + 8) as i16 as f64;
+        let center_y = cpu.memory.read_halfword(src + 10) as i16 as f64;
+        let (pa, pb, pc, pd) = affine(
+            cpu.memory.read_halfword(src + 12),
+            cpu.memory.read_halfword(src + 14),
+            cpu.memory.read_halfword(src + 16),
+        );
+        let start_x = origin_x - (pa * center_x + pb * center_y);
+        let start_y = origin_y - (pc * center_x + pd * center_y);
+
+        cpu.memory.write_halfword(dst, (pa * 256.0) as i16 as u16);
+        cpu.memory.write_halfword(dst + 2, (pb * 256.0) as i16 as u16);
+        cpu.memory.write_halfword(dst + 4, (pc * 256.0) as i16 as u16);
+        cpu.memory.write_halfword(dst + 6, (pd * 256.0) as i16 as u16);
+        cpu.memory.write_word(dst + 8, (start_x * 256.0) as i32 as u32);
+        cpu.memory.write_word(dst + 12, (start_y * 256.0) as i32 as u32);
+        src += 20;
+        dst += 16;
+    }
+}
+
+fn obj_affine_set(cpu: &mut Cpu) {
+    let (mut src, mut dst) = (cpu.registers[R0], cpu.registers[R1]);
+    let stride = cpu.registers[R3];
+    for _ in 0..cpu.registers[R2] {
+        let (pa, pb, pc, pd) = affine(
+            cpu.memory.read_halfword(src),
+            cpu.memory.read_halfword(src + 2),
+            cpu.memory.read_halfword(src + 4),
+        );
+        cpu.memory.write_halfword(dst, (pa * 256.0) as i16 as u16);
+        cpu.memory.write_halfword(dst + stride, (pb * 256.0) as i16 as u16);
+        cpu.memory
+            .write_halfword(dst + stride * 2, (pc * 256.0) as i16 as u16);
+        cpu.memory
+            .write_halfword(dst + stride * 3, (pd * 256.0) as i16 as u16);
+        src += 8;
+        dst += stride * 4;
+    }
+}
+
+// Decompression
+//
+// Compressed data starts with a word holding the decompressed size in bits
+// 8-31.
+
+fn decompressed_size(cpu: &Cpu, src: u32) -> usize {
+    cpu.memory.read_word(src).bits(8..32) as usize
+}
+
+fn lz77_decompress(cpu: &Cpu, src: u32) -> Vec<u8> {
+    let size = decompressed_size(cpu, src);
+    let mut data = Vec::with_capacity(size);
+    let mut src = src + 4;
+
+    while data.len() < size {
+        let flags = cpu.memory.read_byte(src);
+        src += 1;
+        for i in (0..8).rev() {
+            if data.len() >= size {
+                break;
+            }
+            if flags.bit(i) {
+                // A reference to a run of earlier output
+                let a = cpu.memory.read_byte(src) as usize;
+                let b = cpu.memory.read_byte(src + 1) as usize;
+                src += 2;
+                let length = (a >> 4) + 3;
+                let displacement = ((a & 0xF) << 8 | b) + 1;
+                for _ in 0..length {
+                    // Corrupt data can refer to before the start of the
+                    // output, which is taken as zeroes
+                    let byte = if displacement <= data.len() {
+                        data[data.len() - displacement]
+                    } else {
+                        0
+                    };
+                    data.push(byte);
+                }
+            } else {
+                data.push(cpu.memory.read_byte(src));
+                src += 1;
+            }
+        }
+    }
+
+    data.truncate(size);
+    data
+}
+
+fn huffman_decompress(cpu: &Cpu, src: u32) -> Vec<u8> {
+    let size = decompressed_size(cpu, src);
+    // Units are 4 or 8 bits. Any other size is taken as 8 bits, so that
+    // units always fill a word exactly.
+    let unit_size = match cpu.memory.read_word(src).bits(0..4) {
+        4 => 4,
+        _ => 8,
+    };
+    let tree_size = cpu.memory.read_byte(src + 4) as u32;
+    let root = src + 5;
+    let mut stream = src + 4 + (tree_size + 1) * 2;
+    // Children always follow their parent, so a path longer than the tree
+    // has nodes has run off its end. The data is corrupt, and the stream
+    // isn't read any further.
+    let nodes = (tree_size + 1) * 2 - 1;
+
+    let mut data = Vec::with_capacity(size);
+    let mut node = root;
+    let mut depth = 0;
+    let mut word = 0u32;
+    let mut word_bits = 0;
+
+    while data.len() < size {
+        let bits = cpu.memory.read_word(stream);
+        stream = stream.wrapping_add(4);
+        for i in (0..32).rev() {
+            // Each node holds the offset of its pair of children and
+            // whether each child is a leaf
+            let value = cpu.memory.read_byte(node);
+            let right = bits.bit(i);
+            let child = (node & !1) + value.bits(0..6) as u32 * 2 + 2
+                + right as u32;
+            let is_leaf = if right { value.bit(6) } else { value.bit(7) };
+
+            if !is_leaf {
+                depth += 1;
+                if depth > nodes {
+                    return data;
+                }
+                node = child;
+                continue;
+            }
+
+            let unit = cpu.memory.read_byte(child) as u32
+                & ((1 << unit_size) - 1);
+            word |= unit << word_bits;
+            word_bits += unit_size;
+            node = root;
+            depth = 0;
+
+            if word_bits == 32 {
+                let mut bytes = [0; 4];
+                LittleEndian::write_u32(&mut bytes, word);
+                data.extend_from_slice(&bytes);
+                word = 0;
+                word_bits = 0;
+                if data.len() >= size {
+                    break;
+                }
+            }
+        }
+    }
+
+    data.truncate(size);
+    data
+}
+
+fn rl_decompress(cpu: &Cpu, src: u32) -> Vec<u8> {
+    let size = decompressed_size(cpu, src);
+    let mut data = Vec::with_capacity(size);
+    let mut src = src + 4;
+
+    while data.len() < size {
+        let flag = cpu.memory.read_byte(src);
+        src += 1;
+        let length = flag.bits(0..7) as usize;
+        if flag.bit(7) {
+            let byte = cpu.memory.read_byte(src);
+            src += 1;
+            for _ in 0..length + 3 {
+                data.push(byte);
+            }
+        } else {
+            for _ in 0..length + 1 {
+                data.push(cpu.memory.read_byte(src));
+                src += 1;
+            }
+        }
+    }
+
+    data.truncate(size);
+    data
+}
+
+/// Write decompressed data to the destination in r1. VRAM does not support
+/// byte writes, so data bound for it is written a halfword at a time.
+fn write_decompressed(cpu: &mut Cpu, data: &[u8], halfwords: bool) {
+    let dst = cpu.registers[R1];
+    if halfwords {
+        for (i, pair) in data.chunks(2).enumerate() {
+            let high = *pair.get(1).unwrap_or(&0);
+            let value = pair[0] as u16 | (high as u16) << 8;
+            cpu.memory.write_halfword(dst + i as u32 * 2, value);
+        }
+    } else {
+        for (i, &byte) in data.iter().enumerate() {
+            cpu.memory.write_byte(dst + i as u32, byte);
+        }
+    }
+}
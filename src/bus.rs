@@ -1,8 +1,4 @@
-use byteorder::{LittleEndian, ReadBytesExt};
 use std::cell::RefCell;
-use std::fs::File;
-use std::io::*;
-use std::io;
 use std::rc::Rc;
 
 pub trait Read {
@@ -45,22 +41,20 @@ impl<T: Write> Write for Rc<RefCell<T>> {
     }
 }
 
-impl Read for RefCell<BufReader<File>> {
-    fn read_byte(&self, address: u32) -> u8 {
-        let mut inner = self.borrow_mut();
-        inner.seek(io::SeekFrom::Start(address as u64)).unwrap();
-        inner.read_u8().unwrap()
-    }
-
-    fn read_halfword(&self, address: u32) -> u16 {
-        let mut inner = self.borrow_mut();
-        inner.seek(io::SeekFrom::Start(address as u64)).unwrap();
-        inner.read_u16::<LittleEndian>().unwrap()
-    }
+/// The size of a memory access.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Width {
+    Byte,
+    Halfword,
+    Word,
+}
 
-    fn read_word(&self, address: u32) -> u32 {
-        let mut inner = self.borrow_mut();
-        inner.seek(io::SeekFrom::Start(address as u64)).unwrap();
-        inner.read_u32::<LittleEndian>().unwrap()
+impl Width {
+    pub fn bytes(&self) -> u32 {
+        match *self {
+            Width::Byte => 1,
+            Width::Halfword => 2,
+            Width::Word => 4,
+        }
     }
 }
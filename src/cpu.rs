@@ -1,11 +1,12 @@
+use bios::Hle;
 use bit::{Bit, Bits, SetBit, SetBits};
-use bus::{Read, Write};
 use core::ops::Range;
-use decode::decode;
-use execute::execute;
-use instruction::{EncodedInstruction, Instruction};
+use cycles::{self, Cycles};
+use decode::{decode_arm, decode_thumb};
+use execute::{execute, execute_thumb};
+use instruction::EncodedInstruction;
 use interrupt_controller::InterruptController;
-use memory_map::MemoryMap;
+use memory_map::{MemoryMap, PowerMode};
 use std::cell::RefCell;
 use std::ops::{Index, IndexMut};
 use std::rc::Rc;
@@ -21,14 +22,20 @@ pub struct Cpu {
     // Current Program Status Register
     pub cpsr: ProgramStatusRegister,
 
-    // Saved Program Status Register
-    pub spsr: ProgramStatusRegister,
+    // Saved Program Status Registers, banked for each exception mode
+    spsrs: SavedProgramStatusRegisters,
 
     pub memory: MemoryMap,
+
+    // State of the emulated BIOS, if SWIs are serviced natively rather than
+    // by the BIOS ROM
+    pub hle: Option<Hle>,
+
     interrupts: Rc<RefCell<InterruptController>>,
     pipeline: Pipeline,
 }
 
+pub const SP: Register = Register(13);
 pub const LR: Register = Register(14);
 pub const PC: Register = Register(15);
 
@@ -37,25 +44,49 @@ impl Cpu {
         memory: MemoryMap,
         interrupts: Rc<RefCell<InterruptController>>,
     ) -> Cpu {
-        Cpu {
+        let mut cpu = Cpu {
             registers: Registers::new(),
             cpsr: ProgramStatusRegister::new(),
-            spsr: ProgramStatusRegister::new(),
+            spsrs: SavedProgramStatusRegisters::new(),
             memory: memory,
+            hle: None,
             interrupts: interrupts,
             pipeline: Pipeline::new(),
-        }
+        };
+        cpu.exception(Exception::Reset);
+        cpu
     }
 
+    /// Run the CPU for a single instruction, stepping the rest of the system
+    /// by the cycles taken. While the CPU is halted, time skips to the next
+    /// event, as nothing can wake it before then.
     pub fn tick(&mut self) {
+        let cycles = if self.is_halted() {
+            cycles::idle(self.memory.cycles_until_next_event())
+        } else {
+            self.step()
+        };
+
+        // Internal cycles leave the bus free for the prefetch buffer
+        self.memory.idle(cycles.i);
+        let total = cycles.total() + self.memory.take_wait_cycles();
+        self.memory.tick(total);
+    }
+
+    fn step(&mut self) -> Cycles {
         let pc = self.registers[PC];
-        self.pipeline.enqueue(pc);
+        let instruction = self.fetch(pc);
+        self.pipeline.enqueue(pc, instruction);
 
-        if let Some(addr) = self.pipeline.peek() {
-            let bits = self.fetch(addr);
-            let inst = decode(bits);
-            execute(self, inst);
-        }
+        let mut cycles = match self.pipeline.peek() {
+            Some(EncodedInstruction::Arm(bits)) => {
+                execute(self, decode_arm(bits))
+            }
+            Some(EncodedInstruction::Thumb(bits)) => {
+                execute_thumb(self, decode_thumb(bits))
+            }
+            None => cycles::refill(self.pipeline.len() == 1),
+        };
 
         if self.branch_occurred(pc) {
             self.pipeline.flush();
@@ -63,16 +94,115 @@ impl Cpu {
             self.advance_pc();
         }
 
-        if self.interrupts.borrow().is_asserted() {
-            self.handle_interrupt()
+        if self.interrupts.borrow().is_asserted() && !self.cpsr.i() {
+            self.exception(Exception::Irq);
+            cycles = cycles + cycles::interrupt();
+        }
+        cycles
+    }
+
+    /// Continue execution at `address`, discarding the instructions already
+    /// in the pipeline.
+    pub fn jump(&mut self, address: u32) {
+        self.registers[PC] = address;
+        self.pipeline.flush();
+    }
+
+    /// Whether the CPU is in a low power mode. A halted CPU is woken as soon
+    /// as an enabled interrupt is pending, even if IME is clear, and a
+    /// stopped CPU by a keypad, game pak or serial interrupt.
+    fn is_halted(&mut self) -> bool {
+        let woken = match self.memory.power_mode() {
+            PowerMode::Running => return false,
+            PowerMode::Halted => self.interrupts.borrow().is_pending(),
+            PowerMode::Stopped => {
+                self.interrupts.borrow().is_pending_wake_from_stop()
+            }
+        };
+        if woken {
+            self.memory.set_power_mode(PowerMode::Running);
+        }
+        !woken
+    }
+
+    /// Enter an exception, switching to the exception's mode and jumping to
+    /// its vector. The CPSR is saved to the SPSR of the new mode and the
+    /// return address to its LR.
+    ///
+    /// Exceptions caused by an instruction (undefined instruction, SWI and
+    /// aborts) must be raised while that instruction is executing.
+    pub fn exception(&mut self, exception: Exception) {
+        let return_address = self.exception_return_address(exception);
+        let cpsr = self.cpsr;
+
+        let mut exception_cpsr = cpsr;
+        exception_cpsr.set_mode(exception.mode());
+        exception_cpsr.set_t(false);
+        exception_cpsr.set_i(true);
+        if exception == Exception::Reset || exception == Exception::Fiq {
+            exception_cpsr.set_f(true);
+        }
+
+        self.set_cpsr(exception_cpsr);
+        self.set_spsr(cpsr);
+        self.registers[LR] = return_address;
+        self.registers[PC] = exception.vector();
+        self.pipeline.flush();
+    }
+
+    /// Return from an exception by restoring the CPSR from the SPSR of the
+    /// current mode, as done by `movs pc, lr` and `subs pc, lr, #4`.
+    pub fn restore_cpsr(&mut self) {
+        if !self.cpsr.has_spsr() {
+            return;
+        }
+        let spsr = self.spsr();
+        self.set_cpsr(spsr);
+    }
+
+    /// Write the CPSR, switching register banks if the mode has changed.
+    /// All writes that can change the mode must go through this method.
+    pub fn set_cpsr(&mut self, value: ProgramStatusRegister) {
+        self.cpsr = value;
+        self.registers.bank(value.mode());
+    }
+
+    /// Read the SPSR of the current mode. User and System mode have no SPSR,
+    /// in which case the CPSR is returned.
+    pub fn spsr(&self) -> ProgramStatusRegister {
+        match self.spsrs.get(self.cpsr.mode()) {
+            Some(spsr) => *spsr,
+            None => self.cpsr,
+        }
+    }
+
+    /// Write the SPSR of the current mode. Writes are ignored in User and
+    /// System mode.
+    pub fn set_spsr(&mut self, value: ProgramStatusRegister) {
+        if let Some(spsr) = self.spsrs.get_mut(self.cpsr.mode()) {
+            *spsr = value;
+        }
+    }
+
+    fn exception_return_address(&self, exception: Exception) -> u32 {
+        // While an instruction is executing the PC is two instructions ahead
+        // of it.
+        let pc = self.registers[PC];
+        let size = if self.cpsr.t() { 2 } else { 4 };
+        match exception {
+            Exception::Reset => 0,
+            Exception::Undefined | Exception::SoftwareInterrupt => pc - size,
+            Exception::Irq | Exception::Fiq => {
+                self.pipeline.next().unwrap_or(pc) + 4
+            }
         }
     }
 
     fn fetch(&self, address: u32) -> EncodedInstruction {
         if self.cpsr.t() {
-            EncodedInstruction::Thumb(self.memory.read_halfword(address))
+            EncodedInstruction::Thumb(self.memory.fetch_halfword(address))
         } else {
-            EncodedInstruction::Arm(self.memory.read_word(address))
+            EncodedInstruction::Arm(self.memory.fetch_word(address))
         }
     }
 
@@ -84,37 +214,42 @@ impl Cpu {
         let incr = if self.cpsr.t() { 2 } else { 4 };
         self.registers[PC] += incr;
     }
-
-    fn handle_interrupt(&mut self) {
-        self.interrupts.borrow_mut().reset();
-        // R14_irq = address of next instruction to be executed + 4
-        // SPSR_irq = CPSR
-        // CPSR[4:0] = 0b10010
-        // CPSR[5] = 0
-        // /* CPSR[6] is unchanged */
-        // CPSR[7] = 1
-        // PC    = 0x00000018
-    }
 }
 
-/// A fixed length queue of instruction addresses for the CPU to process.
-struct Pipeline((Option<u32>, Option<u32>, Option<u32>));
+/// A fixed length queue of fetched instructions and their addresses for the
+/// CPU to process.
+struct Pipeline((Option<Stage>, Option<Stage>, Option<Stage>));
+
+type Stage = (u32, EncodedInstruction);
 
 impl Pipeline {
     fn new() -> Pipeline {
         Pipeline((None, None, None))
     }
 
-    /// Add a new address to the pipeline, causing the address at the front
-    /// of the queue to be dropped.
-    fn enqueue(&mut self, address: u32) {
+    /// Add a newly fetched instruction to the pipeline, causing the
+    /// instruction at the front of the queue to be dropped.
+    fn enqueue(&mut self, address: u32, instruction: EncodedInstruction) {
         let (a, b, _) = self.0;
-        self.0 = (Some(address), a, b);
+        self.0 = (Some((address, instruction)), a, b);
     }
 
-    /// Get the address at the front of the queue.
-    fn peek(&self) -> Option<u32> {
-        (self.0).2
+    /// Get the instruction at the front of the queue.
+    fn peek(&self) -> Option<EncodedInstruction> {
+        (self.0).2.map(|(_, instruction)| instruction)
+    }
+
+    /// Get the address of the next instruction to be executed, assuming the
+    /// instruction at the back of the queue is about to be enqueued.
+    fn next(&self) -> Option<u32> {
+        let (a, b, _) = self.0;
+        b.or(a).map(|(address, _)| address)
+    }
+
+    /// The number of instructions in the pipeline.
+    fn len(&self) -> usize {
+        let (a, b, c) = self.0;
+        a.iter().chain(b.iter()).chain(c.iter()).count()
     }
 
     /// Empty the pipeline.
@@ -123,6 +258,40 @@ impl Pipeline {
     }
 }
 
+/// The exceptions supported by the ARM7TDMI, each of which is handled in its
+/// own processor mode. Nothing in the system signals an abort, so the
+/// prefetch and data abort exceptions are never taken.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Exception {
+    Reset,
+    Undefined,
+    SoftwareInterrupt,
+    Irq,
+    Fiq,
+}
+
+impl Exception {
+    fn mode(&self) -> Mode {
+        match *self {
+            Exception::Reset => Mode::Supervisor,
+            Exception::Undefined => Mode::Undefined,
+            Exception::SoftwareInterrupt => Mode::Supervisor,
+            Exception::Irq => Mode::Irq,
+            Exception::Fiq => Mode::Fiq,
+        }
+    }
+
+    fn vector(&self) -> u32 {
+        match *self {
+            Exception::Reset => 0x00,
+            Exception::Undefined => 0x04,
+            Exception::SoftwareInterrupt => 0x08,
+            Exception::Irq => 0x18,
+            Exception::Fiq => 0x1C,
+        }
+    }
+}
+
 #[derive(Clone, Copy)]
 pub struct ProgramStatusRegister(u32);
 
@@ -134,17 +303,37 @@ impl ProgramStatusRegister {
     fn mode(&self) -> Mode {
         match self.0.bits(0..5) {
             0b10000 => Mode::User,
-            0b10001 => Mode::FIQ,
-            0b10010 => Mode::IRQ,
+            0b10001 => Mode::Fiq,
+            0b10010 => Mode::Irq,
             0b10011 => Mode::Supervisor,
             0b10111 => Mode::Abort,
             0b11011 => Mode::Undefined,
             0b11111 => Mode::System,
-            _ => panic!("unpredictable!"),
+            // Reserved mode bits are unpredictable. They are kept in the
+            // register but behave like System mode: privileged, using the
+            // user bank and without an SPSR.
+            _ => Mode::System,
         }
     }
 
-    pub fn to_bits(&self) -> u32 {
+    fn set_mode(&mut self, mode: Mode) {
+        let bits = match mode {
+            Mode::User => 0b10000,
+            Mode::Fiq => 0b10001,
+            Mode::Irq => 0b10010,
+            Mode::Supervisor => 0b10011,
+            Mode::Abort => 0b10111,
+            Mode::Undefined => 0b11011,
+            Mode::System => 0b11111,
+        };
+        self.0.set_bits(0..5, bits);
+    }
+
+    pub fn from_bits(bits: u32) -> ProgramStatusRegister {
+        ProgramStatusRegister(bits)
+    }
+
+    pub fn to_bits(self) -> u32 {
         self.0
     }
 
@@ -205,6 +394,10 @@ impl ProgramStatusRegister {
         self.0.set_bit(7, value);
     }
 
+    pub fn set_f(&mut self, value: bool) {
+        self.0.set_bit(6, value);
+    }
+
     pub fn set_t(&mut self, value: bool) {
         self.0.set_bit(5, value);
     }
@@ -214,17 +407,60 @@ impl ProgramStatusRegister {
     }
 }
 
-#[derive(PartialEq)]
+#[derive(Clone, Copy, PartialEq)]
 enum Mode {
     User,
-    FIQ,
-    IRQ,
+    Fiq,
+    Irq,
     Supervisor,
     Abort,
     Undefined,
     System,
 }
 
+/// The SPSRs of each exception mode.
+struct SavedProgramStatusRegisters {
+    supervisor: ProgramStatusRegister,
+    abort: ProgramStatusRegister,
+    undefined: ProgramStatusRegister,
+    irq: ProgramStatusRegister,
+    fiq: ProgramStatusRegister,
+}
+
+impl SavedProgramStatusRegisters {
+    fn new() -> SavedProgramStatusRegisters {
+        SavedProgramStatusRegisters {
+            supervisor: ProgramStatusRegister::new(),
+            abort: ProgramStatusRegister::new(),
+            undefined: ProgramStatusRegister::new(),
+            irq: ProgramStatusRegister::new(),
+            fiq: ProgramStatusRegister::new(),
+        }
+    }
+
+    fn get(&self, mode: Mode) -> Option<&ProgramStatusRegister> {
+        match mode {
+            Mode::Supervisor => Some(&self.supervisor),
+            Mode::Abort => Some(&self.abort),
+            Mode::Undefined => Some(&self.undefined),
+            Mode::Irq => Some(&self.irq),
+            Mode::Fiq => Some(&self.fiq),
+            Mode::User | Mode::System => None,
+        }
+    }
+
+    fn get_mut(&mut self, mode: Mode) -> Option<&mut ProgramStatusRegister> {
+        match mode {
+            Mode::Supervisor => Some(&mut self.supervisor),
+            Mode::Abort => Some(&mut self.abort),
+            Mode::Undefined => Some(&mut self.undefined),
+            Mode::Irq => Some(&mut self.irq),
+            Mode::Fiq => Some(&mut self.fiq),
+            Mode::User | Mode::System => None,
+        }
+    }
+}
+
 #[derive(Clone, Copy, PartialEq)]
 pub struct Register(pub u32);
 
@@ -241,7 +477,7 @@ pub struct Registers {
 impl Registers {
     fn new() -> Registers {
         Registers {
-            mode: Mode::User,
+            mode: Mode::System,
             user: [0; 16],
             supervisor: [0; 2],
             abort: [0; 2],
@@ -254,6 +490,18 @@ impl Registers {
     fn bank(&mut self, mode: Mode) {
         self.mode = mode;
     }
+
+    /// Read a register from the user mode bank, regardless of the current
+    /// mode.
+    pub fn user(&self, index: Register) -> u32 {
+        self.user[index.0 as usize]
+    }
+
+    /// Write a register in the user mode bank, regardless of the current
+    /// mode.
+    pub fn set_user(&mut self, index: Register, value: u32) {
+        self.user[index.0 as usize] = value;
+    }
 }
 
 impl Index<Register> for Registers {
@@ -269,8 +517,8 @@ impl Index<Register> for Registers {
             (&Undefined, 13...14) => {
                 &self.undefined[(index.0 - 13) as usize]
             }
-            (&IRQ, 13...14) => &self.irq[(index.0 - 13) as usize],
-            (&FIQ, 8...14) => &self.fiq[(index.0 - 8) as usize],
+            (&Irq, 13...14) => &self.irq[(index.0 - 13) as usize],
+            (&Fiq, 8...14) => &self.fiq[(index.0 - 8) as usize],
             _ => &self.user[index.0 as usize],
         }
     }
@@ -287,8 +535,8 @@ impl IndexMut<Register> for Registers {
             (&Undefined, 13...14) => {
                 &mut self.undefined[(index.0 - 13) as usize]
             }
-            (&IRQ, 13...14) => &mut self.irq[(index.0 - 13) as usize],
-            (&FIQ, 8...14) => &mut self.fiq[(index.0 - 8) as usize],
+            (&Irq, 13...14) => &mut self.irq[(index.0 - 13) as usize],
+            (&Fiq, 8...14) => &mut self.fiq[(index.0 - 8) as usize],
             _ => &mut self.user[index.0 as usize],
         }
     }
@@ -0,0 +1,248 @@
+use bit::Bits;
+use cpu::{Cpu, Register};
+use instruction::{AddressMode1, AddressingOffset, Instruction,
+                  ThumbInstruction};
+use std::ops::Add;
+
+/// The cycles taken by an instruction, counted by the ARM7TDMI's types of
+/// bus cycle.
+///
+/// Sequential (S) and non-sequential (N) cycles each access memory, and are
+/// lengthened by the wait states of the memory accessed. Internal (I) cycles
+/// don't use the bus.
+///
+/// Every instruction includes the fetch of the instruction two ahead of it.
+/// Refilling the pipeline after a branch isn't included, as it is counted
+/// while the pipeline is refilled.
+#[derive(Clone, Copy, Default)]
+pub struct Cycles {
+    pub s: u32,
+    pub n: u32,
+    pub i: u32,
+}
+
+impl Cycles {
+    pub fn new(s: u32, n: u32, i: u32) -> Cycles {
+        Cycles { s: s, n: n, i: i }
+    }
+
+    /// The number of cycles, before wait states.
+    pub fn total(&self) -> u32 {
+        self.s + self.n + self.i
+    }
+}
+
+impl Add for Cycles {
+    type Output = Cycles;
+
+    fn add(self, other: Cycles) -> Cycles {
+        Cycles::new(self.s + other.s, self.n + other.n, self.i + other.i)
+    }
+}
+
+/// The cycles taken by an ARM instruction whose condition passed. This must
+/// be found before the instruction is executed, as it can depend on
+/// registers the instruction writes.
+pub fn arm(cpu: &Cpu, inst: &Instruction) -> Cycles {
+    match *inst {
+        Instruction::And { operand2, .. }
+        | Instruction::Eor { operand2, .. }
+        | Instruction::Sub { operand2, .. }
+        | Instruction::Rsb { operand2, .. }
+        | Instruction::Add { operand2, .. }
+        | Instruction::Adc { operand2, .. }
+        | Instruction::Sbc { operand2, .. }
+        | Instruction::Rsc { operand2, .. }
+        | Instruction::Tst { operand2, .. }
+        | Instruction::Teq { operand2, .. }
+        | Instruction::Cmp { operand2, .. }
+        | Instruction::Cmn { operand2, .. }
+        | Instruction::Orr { operand2, .. }
+        | Instruction::Mov { operand2, .. }
+        | Instruction::Bic { operand2, .. }
+        | Instruction::Mvn { operand2, .. } => match operand2 {
+            // Shifting by a register takes an extra cycle to read it
+            AddressMode1::Shift {
+                shift_imm: AddressingOffset::Register(_),
+                ..
+            } => Cycles::new(1, 0, 1),
+            _ => Cycles::new(1, 0, 0),
+        },
+
+        Instruction::Mul { rs, .. } => {
+            Cycles::new(1, 0, multiplier_cycles(cpu, rs, true))
+        }
+        Instruction::Mla { rs, .. } | Instruction::Smull { rs, .. } => {
+            Cycles::new(1, 0, multiplier_cycles(cpu, rs, true) + 1)
+        }
+        Instruction::Smlal { rs, .. } => {
+            Cycles::new(1, 0, multiplier_cycles(cpu, rs, true) + 2)
+        }
+        Instruction::Umull { rs, .. } => {
+            Cycles::new(1, 0, multiplier_cycles(cpu, rs, false) + 1)
+        }
+        Instruction::Umlal { rs, .. } => {
+            Cycles::new(1, 0, multiplier_cycles(cpu, rs, false) + 2)
+        }
+
+        Instruction::Ldrh { .. }
+        | Instruction::Ldrsb { .. }
+        | Instruction::Ldrsh { .. }
+        | Instruction::Ldrbt { .. }
+        | Instruction::Ldrt { .. }
+        | Instruction::Ldrb { .. }
+        | Instruction::Ldr { .. } => load(),
+        Instruction::Strh { .. }
+        | Instruction::Strbt { .. }
+        | Instruction::Strt { .. }
+        | Instruction::Strb { .. }
+        | Instruction::Str { .. } => store(),
+
+        Instruction::Ldm1 { register_list, .. }
+        | Instruction::Ldm2 { register_list, .. }
+        | Instruction::Ldm3 { register_list, .. } => {
+            load_multiple(register_list)
+        }
+        Instruction::Stm1 { register_list, .. }
+        | Instruction::Stm2 { register_list, .. } => {
+            store_multiple(register_list)
+        }
+
+        Instruction::Swp { .. } | Instruction::Swpb { .. } => {
+            Cycles::new(1, 2, 1)
+        }
+
+        // Undefined and coprocessor instructions take the undefined
+        // instruction trap
+        Instruction::Undefined { .. }
+        | Instruction::Cdp { .. }
+        | Instruction::Ldc { .. }
+        | Instruction::Mcr { .. }
+        | Instruction::Mrc { .. }
+        | Instruction::Stc { .. } => Cycles::new(1, 0, 1),
+
+        Instruction::B { .. }
+        | Instruction::Bx { .. }
+        | Instruction::Msr { .. }
+        | Instruction::Mrs { .. }
+        | Instruction::Swi { .. } => Cycles::new(1, 0, 0),
+    }
+}
+
+/// The cycles taken by a Thumb instruction. This must be found before the
+/// instruction is executed, as it can depend on registers the instruction
+/// writes.
+pub fn thumb(cpu: &Cpu, inst: &ThumbInstruction) -> Cycles {
+    match *inst {
+        ThumbInstruction::Asr2 { .. }
+        | ThumbInstruction::Lsl2 { .. }
+        | ThumbInstruction::Lsr2 { .. }
+        | ThumbInstruction::Ror { .. } => Cycles::new(1, 0, 1),
+
+        // The multiplier is the original value of Rd
+        ThumbInstruction::Mul { rd, .. } => {
+            Cycles::new(1, 0, multiplier_cycles(cpu, rd, true))
+        }
+
+        ThumbInstruction::Ldr1 { .. }
+        | ThumbInstruction::Ldr2 { .. }
+        | ThumbInstruction::Ldr3 { .. }
+        | ThumbInstruction::Ldr4 { .. }
+        | ThumbInstruction::Ldrb1 { .. }
+        | ThumbInstruction::Ldrb2 { .. }
+        | ThumbInstruction::Ldrh1 { .. }
+        | ThumbInstruction::Ldrh2 { .. }
+        | ThumbInstruction::Ldrsb { .. }
+        | ThumbInstruction::Ldrsh { .. } => load(),
+        ThumbInstruction::Str1 { .. }
+        | ThumbInstruction::Str2 { .. }
+        | ThumbInstruction::Str3 { .. }
+        | ThumbInstruction::Strb1 { .. }
+        | ThumbInstruction::Strb2 { .. }
+        | ThumbInstruction::Strh1 { .. }
+        | ThumbInstruction::Strh2 { .. } => store(),
+
+        // POP and PUSH transfer PC or LR too if R is set
+        ThumbInstruction::Ldmia { register_list, .. } => {
+            load_multiple(register_list)
+        }
+        ThumbInstruction::Pop { r, register_list } => {
+            load_multiple(register_list | (r as u32) << 15)
+        }
+        ThumbInstruction::Stmia { register_list, .. } => {
+            store_multiple(register_list)
+        }
+        ThumbInstruction::Push { r, register_list } => {
+            store_multiple(register_list | (r as u32) << 14)
+        }
+
+        _ => Cycles::new(1, 0, 0),
+    }
+}
+
+/// The cycles taken by an ARM instruction whose condition failed.
+pub fn skipped() -> Cycles {
+    Cycles::new(1, 0, 0)
+}
+
+/// The cycles taken by each fetch while the pipeline is refilled after a
+/// branch. The first is from the branch target, so is non-sequential.
+pub fn refill(first: bool) -> Cycles {
+    if first {
+        Cycles::new(0, 1, 0)
+    } else {
+        Cycles::new(1, 0, 0)
+    }
+}
+
+/// The cycles spent while the CPU is halted or stopped.
+pub fn idle(cycles: u32) -> Cycles {
+    Cycles::new(0, 0, cycles)
+}
+
+/// The cycles taken to enter an exception on an interrupt, not including
+/// the fetches to refill the pipeline. This is a fetch which is discarded.
+pub fn interrupt() -> Cycles {
+    Cycles::new(1, 0, 0)
+}
+
+// Loads take an internal cycle to write the loaded value to the register
+fn load() -> Cycles {
+    Cycles::new(1, 1, 1)
+}
+
+fn store() -> Cycles {
+    Cycles::new(0, 2, 0)
+}
+
+fn load_multiple(register_list: u32) -> Cycles {
+    Cycles::new(transfer_count(register_list), 1, 1)
+}
+
+fn store_multiple(register_list: u32) -> Cycles {
+    Cycles::new(transfer_count(register_list) - 1, 2, 0)
+}
+
+// An empty register list transfers PC
+fn transfer_count(register_list: u32) -> u32 {
+    register_list.bits(0..16).count_ones().max(1)
+}
+
+// The multiplier finishes early when the remaining bits of the multiplier
+// operand are all zero, or for signed multiplies all one.
+fn multiplier_cycles(cpu: &Cpu, rs: Register, signed: bool) -> u32 {
+    let value = cpu.registers[rs];
+    let finished = |bit: u8| {
+        let remaining = value >> bit;
+        remaining == 0 || signed && remaining == 0xFFFFFFFF >> bit
+    };
+    if finished(8) {
+        1
+    } else if finished(16) {
+        2
+    } else if finished(24) {
+        3
+    } else {
+        4
+    }
+}
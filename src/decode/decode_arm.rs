@@ -1,7 +1,8 @@
 use bit::{Bit, Bits};
 use cpu::Register;
-use instruction::{AddressMode1, AddressMode2, AddressMode3, AddressingMode,
-                  AddressingOffset, Condition, Instruction, ShiftDirection};
+use instruction::{AddressMode1, AddressMode2, AddressMode3, AddressMode4,
+                  AddressMode5, AddressingMode, AddressingOffset, Condition,
+                  Instruction, ShiftDirection};
 
 pub fn decode_arm(inst: u32) -> Instruction {
     let bits = (
@@ -32,6 +33,8 @@ pub fn decode_arm(inst: u32) -> Instruction {
         (0, 0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 1) => bx(inst),
         (0, 0, 0, 1, 0, 0, 0, 0, 1, 0, 0, 1) => swp(inst),
         (0, 0, 0, 1, 0, 1, 0, 0, 1, 0, 0, 1) => swpb(inst),
+        // Post-indexed halfword transfers with W set are unpredictable
+        (0, 0, 0, 0, _, _, 1, _, 1, _, _, 1) => undefined(inst),
         (0, 0, 0, _, _, _, _, 1, 1, 0, 1, 1) => ldrh(inst),
         (0, 0, 0, _, _, _, _, 0, 1, 0, 1, 1) => strh(inst),
         (0, 0, 0, _, _, _, _, 1, 1, 1, 0, 1) => ldrsb(inst),
@@ -52,29 +55,28 @@ pub fn decode_arm(inst: u32) -> Instruction {
         (0, 0, _, 1, 0, 0, 1, 1, _, _, _, _) => teq(inst),
         (0, 0, _, 1, 0, 1, 0, 1, _, _, _, _) => cmp(inst),
         (0, 0, _, 1, 0, 1, 1, 1, _, _, _, _) => cmn(inst),
-        (0, 1, _, _, _, 0, _, 1, _, _, _, _) => ldr(inst),
-        (0, 1, _, _, _, 1, _, 1, _, _, _, _) => ldrb(inst),
-        (0, 1, _, _, _, 0, _, 0, _, _, _, _) => str(inst),
-        (0, 1, _, _, _, 1, _, 0, _, _, _, _) => strb(inst),
+        (0, 1, 1, _, _, _, _, _, _, _, _, 1) => undefined(inst),
         (0, 1, _, 0, _, 1, 1, 1, _, _, _, _) => ldrbt(inst),
         (0, 1, _, 0, _, 0, 1, 1, _, _, _, _) => ldrt(inst),
         (0, 1, _, 0, _, 1, 1, 0, _, _, _, _) => strbt(inst),
         (0, 1, _, 0, _, 0, 1, 0, _, _, _, _) => strt(inst),
+        (0, 1, _, _, _, 0, _, 1, _, _, _, _) => ldr(inst),
+        (0, 1, _, _, _, 1, _, 1, _, _, _, _) => ldrb(inst),
+        (0, 1, _, _, _, 0, _, 0, _, _, _, _) => str(inst),
+        (0, 1, _, _, _, 1, _, 0, _, _, _, _) => strb(inst),
         (1, 0, 0, _, _, 0, _, 1, _, _, _, _) => ldm1(inst),
-        (1, 0, 0, _, _, 1, _, 1, _, _, _, _) => ldm3(inst),
+        (1, 0, 0, _, _, 1, _, 1, _, _, _, _) if inst.bit(15) => ldm3(inst),
         (1, 0, 0, _, _, 0, _, 0, _, _, _, _) => stm1(inst),
         (1, 0, 0, _, _, 1, 0, 1, _, _, _, _) => ldm2(inst),
         (1, 0, 0, _, _, 1, 0, 0, _, _, _, _) => stm2(inst),
         (1, 0, 1, _, _, _, _, _, _, _, _, _) => b(inst),
         (1, 1, 0, _, _, _, _, 1, _, _, _, _) => ldc(inst),
         (1, 1, 0, _, _, _, _, 0, _, _, _, _) => stc(inst),
-        (1, 1, 1, 0, _, _, _, 1, _, _, _, 0) => cdp(inst),
+        (1, 1, 1, 0, _, _, _, _, _, _, _, 0) => cdp(inst),
         (1, 1, 1, 0, _, _, _, 0, _, _, _, 1) => mcr(inst),
         (1, 1, 1, 0, _, _, _, 1, _, _, _, 1) => mrc(inst),
         (1, 1, 1, 1, _, _, _, _, _, _, _, _) => swi(inst),
-        _ => {
-            panic!("Unrecognised instruction: {:x}", inst);
-        }
+        _ => undefined(inst),
     }
 }
 
@@ -459,30 +461,40 @@ fn strt(inst: u32) -> Instruction {
 fn ldm1(inst: u32) -> Instruction {
     Instruction::Ldm1 {
         condition: condition(inst),
+        address: decode_address_mode_4(inst),
+        register_list: inst.bits(0..16),
     }
 }
 
 fn ldm3(inst: u32) -> Instruction {
     Instruction::Ldm3 {
         condition: condition(inst),
+        address: decode_address_mode_4(inst),
+        register_list: inst.bits(0..16),
     }
 }
 
 fn stm1(inst: u32) -> Instruction {
     Instruction::Stm1 {
         condition: condition(inst),
+        address: decode_address_mode_4(inst),
+        register_list: inst.bits(0..16),
     }
 }
 
 fn ldm2(inst: u32) -> Instruction {
     Instruction::Ldm2 {
         condition: condition(inst),
+        address: decode_address_mode_4(inst),
+        register_list: inst.bits(0..16),
     }
 }
 
 fn stm2(inst: u32) -> Instruction {
     Instruction::Stm2 {
         condition: condition(inst),
+        address: decode_address_mode_4(inst),
+        register_list: inst.bits(0..16),
     }
 }
 
@@ -497,30 +509,56 @@ fn b(inst: u32) -> Instruction {
 fn ldc(inst: u32) -> Instruction {
     Instruction::Ldc {
         condition: condition(inst),
+        n: inst.bit(22),
+        cp_num: inst.bits(8..12),
+        crd: inst.bits(12..16),
+        address: decode_address_mode_5(inst),
     }
 }
 
 fn stc(inst: u32) -> Instruction {
     Instruction::Stc {
         condition: condition(inst),
+        n: inst.bit(22),
+        cp_num: inst.bits(8..12),
+        crd: inst.bits(12..16),
+        address: decode_address_mode_5(inst),
     }
 }
 
 fn cdp(inst: u32) -> Instruction {
     Instruction::Cdp {
         condition: condition(inst),
+        cp_num: inst.bits(8..12),
+        opcode_1: inst.bits(20..24),
+        crd: inst.bits(12..16),
+        crn: inst.bits(16..20),
+        crm: inst.bits(0..4),
+        opcode_2: inst.bits(5..8),
     }
 }
 
 fn mcr(inst: u32) -> Instruction {
     Instruction::Mcr {
         condition: condition(inst),
+        cp_num: inst.bits(8..12),
+        opcode_1: inst.bits(21..24),
+        rd: Register(inst.bits(12..16)),
+        crn: inst.bits(16..20),
+        crm: inst.bits(0..4),
+        opcode_2: inst.bits(5..8),
     }
 }
 
 fn mrc(inst: u32) -> Instruction {
     Instruction::Mrc {
         condition: condition(inst),
+        cp_num: inst.bits(8..12),
+        opcode_1: inst.bits(21..24),
+        rd: Register(inst.bits(12..16)),
+        crn: inst.bits(16..20),
+        crm: inst.bits(0..4),
+        opcode_2: inst.bits(5..8),
     }
 }
 
@@ -531,6 +569,13 @@ fn swi(inst: u32) -> Instruction {
     }
 }
 
+fn undefined(inst: u32) -> Instruction {
+    Instruction::Undefined {
+        condition: condition(inst),
+        inst: inst,
+    }
+}
+
 fn decode_address_mode_1(inst: u32) -> AddressMode1 {
     if inst.bit(25) {
         AddressMode1::Immediate {
@@ -589,17 +634,13 @@ fn decode_address_mode_2(inst: u32) -> AddressMode2 {
         AddressingOffset::Immediate(offset as u16)
     };
 
+    // Post-indexed transfers with W set are the user mode LDRT, STRT, LDRBT
+    // and STRBT, which are told apart by their instruction
     let addressing = if p && w {
         AddressingMode::PreIndexed
     } else if p && !w {
         AddressingMode::Offset
-    } else if !p && w {
-        panic!("unpredictable")
-    }
-    // TODO: not unpredictable for address mode 2
-    else
-    /* !p && !w  */
-    {
+    } else {
         AddressingMode::PostIndexed
     };
 
@@ -628,15 +669,12 @@ fn decode_address_mode_3(inst: u32) -> AddressMode3 {
         AddressingOffset::Register(register)
     };
 
+    // Post-indexed transfers with W set are decoded as undefined
     let addressing = if p && w {
         AddressingMode::PreIndexed
     } else if p && !w {
         AddressingMode::Offset
-    } else if !p && w {
-        panic!("unpredictable")
-    } else
-    /* !p && !w  */
-    {
+    } else {
         AddressingMode::PostIndexed
     };
 
@@ -647,3 +685,22 @@ fn decode_address_mode_3(inst: u32) -> AddressMode3 {
         u: u,
     }
 }
+
+fn decode_address_mode_4(inst: u32) -> AddressMode4 {
+    AddressMode4 {
+        rn: Register(inst.bits(16..20)),
+        p: inst.bit(24),
+        u: inst.bit(23),
+        w: inst.bit(21),
+    }
+}
+
+fn decode_address_mode_5(inst: u32) -> AddressMode5 {
+    AddressMode5 {
+        rn: Register(inst.bits(16..20)),
+        offset_8: inst.bits(0..8),
+        p: inst.bit(24),
+        u: inst.bit(23),
+        w: inst.bit(21),
+    }
+}
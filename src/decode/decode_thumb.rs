@@ -1,5 +1,568 @@
-use instruction::*;
+use bit::{Bit, Bits};
+use cpu::Register;
+use instruction::{Condition, ThumbInstruction};
 
-pub fn decode_thumb(inst: u16) -> Instruction {
-    unimplemented!();
+pub fn decode_thumb(inst: u16) -> ThumbInstruction {
+    let inst = inst as u32;
+    let bits = (
+        inst.bit(15) as u8,
+        inst.bit(14) as u8,
+        inst.bit(13) as u8,
+        inst.bit(12) as u8,
+        inst.bit(11) as u8,
+        inst.bit(10) as u8,
+        inst.bit(9) as u8,
+        inst.bit(8) as u8,
+        inst.bit(7) as u8,
+        inst.bit(6) as u8,
+    );
+
+    match bits {
+        (0, 0, 0, 0, 0, _, _, _, _, _) => lsl1(inst),
+        (0, 0, 0, 0, 1, _, _, _, _, _) => lsr1(inst),
+        (0, 0, 0, 1, 0, _, _, _, _, _) => asr1(inst),
+        (0, 0, 0, 1, 1, 0, 0, _, _, _) => add3(inst),
+        (0, 0, 0, 1, 1, 0, 1, _, _, _) => sub3(inst),
+        (0, 0, 0, 1, 1, 1, 0, 0, 0, 0) => mov2(inst),
+        (0, 0, 0, 1, 1, 1, 0, _, _, _) => add1(inst),
+        (0, 0, 0, 1, 1, 1, 1, _, _, _) => sub1(inst),
+        (0, 0, 1, 0, 0, _, _, _, _, _) => mov1(inst),
+        (0, 0, 1, 0, 1, _, _, _, _, _) => cmp1(inst),
+        (0, 0, 1, 1, 0, _, _, _, _, _) => add2(inst),
+        (0, 0, 1, 1, 1, _, _, _, _, _) => sub2(inst),
+        (0, 1, 0, 0, 0, 0, 0, 0, 0, 0) => and(inst),
+        (0, 1, 0, 0, 0, 0, 0, 0, 0, 1) => eor(inst),
+        (0, 1, 0, 0, 0, 0, 0, 0, 1, 0) => lsl2(inst),
+        (0, 1, 0, 0, 0, 0, 0, 0, 1, 1) => lsr2(inst),
+        (0, 1, 0, 0, 0, 0, 0, 1, 0, 0) => asr2(inst),
+        (0, 1, 0, 0, 0, 0, 0, 1, 0, 1) => adc(inst),
+        (0, 1, 0, 0, 0, 0, 0, 1, 1, 0) => sbc(inst),
+        (0, 1, 0, 0, 0, 0, 0, 1, 1, 1) => ror(inst),
+        (0, 1, 0, 0, 0, 0, 1, 0, 0, 0) => tst(inst),
+        (0, 1, 0, 0, 0, 0, 1, 0, 0, 1) => neg(inst),
+        (0, 1, 0, 0, 0, 0, 1, 0, 1, 0) => cmp2(inst),
+        (0, 1, 0, 0, 0, 0, 1, 0, 1, 1) => cmn(inst),
+        (0, 1, 0, 0, 0, 0, 1, 1, 0, 0) => orr(inst),
+        (0, 1, 0, 0, 0, 0, 1, 1, 0, 1) => mul(inst),
+        (0, 1, 0, 0, 0, 0, 1, 1, 1, 0) => bic(inst),
+        (0, 1, 0, 0, 0, 0, 1, 1, 1, 1) => mvn(inst),
+        (0, 1, 0, 0, 0, 1, 0, 0, _, _) => add4(inst),
+        (0, 1, 0, 0, 0, 1, 0, 1, _, _) => cmp3(inst),
+        (0, 1, 0, 0, 0, 1, 1, 0, _, _) => mov3(inst),
+        (0, 1, 0, 0, 0, 1, 1, 1, 0, _) => bx(inst),
+        (0, 1, 0, 0, 1, _, _, _, _, _) => ldr3(inst),
+        (0, 1, 0, 1, 0, 0, 0, _, _, _) => str2(inst),
+        (0, 1, 0, 1, 0, 0, 1, _, _, _) => strh2(inst),
+        (0, 1, 0, 1, 0, 1, 0, _, _, _) => strb2(inst),
+        (0, 1, 0, 1, 0, 1, 1, _, _, _) => ldrsb(inst),
+        (0, 1, 0, 1, 1, 0, 0, _, _, _) => ldr2(inst),
+        (0, 1, 0, 1, 1, 0, 1, _, _, _) => ldrh2(inst),
+        (0, 1, 0, 1, 1, 1, 0, _, _, _) => ldrb2(inst),
+        (0, 1, 0, 1, 1, 1, 1, _, _, _) => ldrsh(inst),
+        (0, 1, 1, 0, 0, _, _, _, _, _) => str1(inst),
+        (0, 1, 1, 0, 1, _, _, _, _, _) => ldr1(inst),
+        (0, 1, 1, 1, 0, _, _, _, _, _) => strb1(inst),
+        (0, 1, 1, 1, 1, _, _, _, _, _) => ldrb1(inst),
+        (1, 0, 0, 0, 0, _, _, _, _, _) => strh1(inst),
+        (1, 0, 0, 0, 1, _, _, _, _, _) => ldrh1(inst),
+        (1, 0, 0, 1, 0, _, _, _, _, _) => str3(inst),
+        (1, 0, 0, 1, 1, _, _, _, _, _) => ldr4(inst),
+        (1, 0, 1, 0, 0, _, _, _, _, _) => add5(inst),
+        (1, 0, 1, 0, 1, _, _, _, _, _) => add6(inst),
+        (1, 0, 1, 1, 0, 0, 0, 0, 0, _) => add7(inst),
+        (1, 0, 1, 1, 0, 0, 0, 0, 1, _) => sub4(inst),
+        (1, 0, 1, 1, 0, 1, 0, _, _, _) => push(inst),
+        (1, 0, 1, 1, 1, 1, 0, _, _, _) => pop(inst),
+        (1, 1, 0, 0, 0, _, _, _, _, _) => stmia(inst),
+        (1, 1, 0, 0, 1, _, _, _, _, _) => ldmia(inst),
+        (1, 1, 0, 1, 1, 1, 1, 1, _, _) => swi(inst),
+        (1, 1, 0, 1, 1, 1, 1, 0, _, _) => undefined(inst),
+        (1, 1, 0, 1, _, _, _, _, _, _) => b1(inst),
+        (1, 1, 1, 0, 0, _, _, _, _, _) => b2(inst),
+        (1, 1, 1, 1, _, _, _, _, _, _) => bl(inst),
+        _ => undefined(inst),
+    }
+}
+
+fn condition(inst: u32) -> Condition {
+    match inst.bits(8..12) {
+        0b0000 => Condition::Eq,
+        0b0001 => Condition::Ne,
+        0b0010 => Condition::Cs,
+        0b0011 => Condition::Cc,
+        0b0100 => Condition::Mi,
+        0b0101 => Condition::Pl,
+        0b0110 => Condition::Vs,
+        0b0111 => Condition::Vc,
+        0b1000 => Condition::Hi,
+        0b1001 => Condition::Ls,
+        0b1010 => Condition::Ge,
+        0b1011 => Condition::Lt,
+        0b1100 => Condition::Gt,
+        0b1101 => Condition::Le,
+        _ => unreachable!(),
+    }
+}
+
+// Shift by immediate, add/subtract register and immediate
+
+fn lsl1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Lsl1 {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn lsr1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Lsr1 {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn asr1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Asr1 {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn add3(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add3 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn sub3(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Sub3 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn mov2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Mov2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+    }
+}
+
+fn add1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_3: inst.bits(6..9),
+    }
+}
+
+fn sub1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Sub1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_3: inst.bits(6..9),
+    }
+}
+
+// Add/subtract/compare/move immediate
+
+fn mov1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Mov1 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn cmp1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Cmp1 {
+        rn: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn add2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add2 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn sub2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Sub2 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+// Data-processing register
+
+fn and(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::And {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn eor(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Eor {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn lsl2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Lsl2 {
+        rd: Register(inst.bits(0..3)),
+        rs: Register(inst.bits(3..6)),
+    }
+}
+
+fn lsr2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Lsr2 {
+        rd: Register(inst.bits(0..3)),
+        rs: Register(inst.bits(3..6)),
+    }
+}
+
+fn asr2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Asr2 {
+        rd: Register(inst.bits(0..3)),
+        rs: Register(inst.bits(3..6)),
+    }
+}
+
+fn adc(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Adc {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn sbc(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Sbc {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn ror(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ror {
+        rd: Register(inst.bits(0..3)),
+        rs: Register(inst.bits(3..6)),
+    }
+}
+
+fn tst(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Tst {
+        rn: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn neg(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Neg {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn cmp2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Cmp2 {
+        rn: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn cmn(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Cmn {
+        rn: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn orr(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Orr {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn mul(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Mul {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn bic(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Bic {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+fn mvn(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Mvn {
+        rd: Register(inst.bits(0..3)),
+        rm: Register(inst.bits(3..6)),
+    }
+}
+
+// Special data processing and branch/exchange
+
+fn add4(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add4 {
+        rd: Register(inst.bits(0..3) | (inst.bit(7) as u32) << 3),
+        rm: Register(inst.bits(3..7)),
+    }
+}
+
+fn cmp3(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Cmp3 {
+        rn: Register(inst.bits(0..3) | (inst.bit(7) as u32) << 3),
+        rm: Register(inst.bits(3..7)),
+    }
+}
+
+fn mov3(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Mov3 {
+        rd: Register(inst.bits(0..3) | (inst.bit(7) as u32) << 3),
+        rm: Register(inst.bits(3..7)),
+    }
+}
+
+fn bx(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Bx {
+        rm: Register(inst.bits(3..7)),
+    }
+}
+
+// Load/store register
+
+fn ldr3(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldr3 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn str2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Str2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn strh2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Strh2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn strb2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Strb2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn ldrsb(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldrsb {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn ldr2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldr2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn ldrh2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldrh2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn ldrb2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldrb2 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn ldrsh(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldrsh {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        rm: Register(inst.bits(6..9)),
+    }
+}
+
+fn str1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Str1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn ldr1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldr1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn strb1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Strb1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn ldrb1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldrb1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn strh1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Strh1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn ldrh1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldrh1 {
+        rd: Register(inst.bits(0..3)),
+        rn: Register(inst.bits(3..6)),
+        immed_5: inst.bits(6..11),
+    }
+}
+
+fn str3(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Str3 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn ldr4(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldr4 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+// Add to SP or PC, adjust stack pointer
+
+fn add5(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add5 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn add6(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add6 {
+        rd: Register(inst.bits(8..11)),
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn add7(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Add7 {
+        immed_7: inst.bits(0..7),
+    }
+}
+
+fn sub4(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Sub4 {
+        immed_7: inst.bits(0..7),
+    }
+}
+
+// Load/store multiple
+
+fn push(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Push {
+        r: inst.bit(8),
+        register_list: inst.bits(0..8),
+    }
+}
+
+fn pop(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Pop {
+        r: inst.bit(8),
+        register_list: inst.bits(0..8),
+    }
+}
+
+fn stmia(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Stmia {
+        rn: Register(inst.bits(8..11)),
+        register_list: inst.bits(0..8),
+    }
+}
+
+fn ldmia(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Ldmia {
+        rn: Register(inst.bits(8..11)),
+        register_list: inst.bits(0..8),
+    }
+}
+
+// Branch and exception generating
+
+fn swi(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Swi {
+        immed_8: inst.bits(0..8),
+    }
+}
+
+fn undefined(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Undefined { inst: inst }
+}
+
+fn b1(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::B1 {
+        condition: condition(inst),
+        signed_immed_8: inst.bits(0..8),
+    }
+}
+
+fn b2(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::B2 {
+        signed_immed_11: inst.bits(0..11),
+    }
+}
+
+fn bl(inst: u32) -> ThumbInstruction {
+    ThumbInstruction::Bl {
+        h: inst.bits(11..13),
+        offset_11: inst.bits(0..11),
+    }
 }
@@ -0,0 +1,331 @@
+use bit::{Bit, Bits, SetBit, SetBits};
+use bus::{Read, Width, Write};
+use interrupt_controller::{Input, InterruptController};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// When a channel transfers once enabled.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Timing {
+    Immediate,
+    VBlank,
+    HBlank,
+    // Requested by the sound FIFOs for channels 1 and 2, or each line for
+    // video capture on channel 3
+    Special,
+}
+
+/// The four DMA channels, which copy blocks of memory while the CPU is
+/// stalled.
+///
+/// The channels don't access memory themselves. The memory map takes the
+/// next transfer due, makes it over the bus and then finishes it here.
+pub struct Dma {
+    channels: [Channel; 4],
+    interrupts: Rc<RefCell<InterruptController>>,
+}
+
+struct Channel {
+    source: u32,
+    destination: u32,
+    count: u16,
+    control: u16,
+    // Internal registers, loaded from the registers above when the channel
+    // is enabled and updated as it transfers
+    internal_source: u32,
+    internal_destination: u32,
+    internal_count: u32,
+    pending: bool,
+}
+
+impl Channel {
+    fn new() -> Channel {
+        Channel {
+            source: 0,
+            destination: 0,
+            count: 0,
+            control: 0,
+            internal_source: 0,
+            internal_destination: 0,
+            internal_count: 0,
+            pending: false,
+        }
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.control.bit(15)
+    }
+
+    fn timing(&self) -> Timing {
+        match self.control.bits(12..14) {
+            0 => Timing::Immediate,
+            1 => Timing::VBlank,
+            2 => Timing::HBlank,
+            _ => Timing::Special,
+        }
+    }
+
+    fn repeats(&self) -> bool {
+        self.control.bit(9)
+    }
+
+    fn reloads_destination(&self) -> bool {
+        self.control.bits(5..7) == 3
+    }
+}
+
+/// A block transfer by a channel.
+pub struct Transfer {
+    pub channel: usize,
+    pub source: u32,
+    pub destination: u32,
+    pub count: u32,
+    pub width: Width,
+    // The change in address after each unit, as a two's complement offset
+    pub source_step: u32,
+    pub destination_step: u32,
+}
+
+impl Dma {
+    pub fn new(interrupts: Rc<RefCell<InterruptController>>) -> Dma {
+        Dma {
+            channels: [
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+                Channel::new(),
+            ],
+            interrupts: interrupts,
+        }
+    }
+
+    /// Start the enabled channels waiting for V-Blank or H-Blank.
+    pub fn trigger(&mut self, timing: Timing) {
+        for channel in self.channels.iter_mut() {
+            if channel.is_enabled() && channel.timing() == timing {
+                channel.pending = true;
+            }
+        }
+    }
+
+    /// Start the channel feeding the sound FIFO at `fifo`, which has run low.
+    pub fn request_sound(&mut self, fifo: u32) {
+        for channel in self.channels[1..3].iter_mut() {
+            if channel.is_enabled() && channel.timing() == Timing::Special
+                && channel.destination == fifo
+            {
+                channel.pending = true;
+            }
+        }
+    }
+
+    /// Start channel 3 for a line of video capture.
+    pub fn request_video_capture(&mut self) {
+        let channel = &mut self.channels[3];
+        if channel.is_enabled() && channel.timing() == Timing::Special {
+            channel.pending = true;
+        }
+    }
+
+    /// Stop video capture at the end of the captured lines.
+    pub fn stop_video_capture(&mut self) {
+        let channel = &mut self.channels[3];
+        if channel.timing() == Timing::Special {
+            channel.control.set_bit(15, false);
+            channel.pending = false;
+        }
+    }
+
+    /// Take the transfer of the highest priority channel which has been
+    /// started, if any. Lower numbered channels take priority.
+    pub fn next_transfer(&mut self) -> Option<Transfer> {
+        let index = self.channels.iter().position(|channel| channel.pending)?;
+        let channel = &mut self.channels[index];
+        channel.pending = false;
+
+        // Sound FIFO transfers are always of four words to the FIFO
+        let sound = (index == 1 || index == 2)
+            && channel.timing() == Timing::Special;
+        let width = if sound || channel.control.bit(10) {
+            Width::Word
+        } else {
+            Width::Halfword
+        };
+        let size = width.bytes();
+        let step = |mode| match mode {
+            0 | 3 => size,
+            1 => size.wrapping_neg(),
+            _ => 0,
+        };
+
+        let destination_step = if sound {
+            0
+        } else {
+            step(channel.control.bits(5..7))
+        };
+        // The game pak can only be read in ascending order
+        let source_step = match channel.internal_source {
+            0x8000000...0xDFFFFFF => size,
+            _ => step(channel.control.bits(7..9)),
+        };
+
+        Some(Transfer {
+            channel: index,
+            source: channel.internal_source & !(size - 1),
+            destination: channel.internal_destination & !(size - 1),
+            count: if sound { 4 } else { channel.internal_count },
+            width: width,
+            source_step: source_step,
+            destination_step: destination_step,
+        })
+    }
+
+    /// Finish a transfer once it has been made, leaving the channel ready to
+    /// repeat or disabling it.
+    pub fn finish(&mut self, transfer: &Transfer) {
+        let index = transfer.channel;
+        let channel = &mut self.channels[index];
+        channel.internal_source = transfer
+            .source
+            .wrapping_add(transfer.source_step.wrapping_mul(transfer.count));
+        channel.internal_destination = transfer.destination.wrapping_add(
+            transfer.destination_step.wrapping_mul(transfer.count),
+        );
+
+        if channel.repeats() && channel.timing() != Timing::Immediate {
+            channel.internal_count = transfer_count(index, channel.count);
+            if channel.reloads_destination() {
+                channel.internal_destination =
+                    destination_mask(index, channel.destination);
+            }
+        } else {
+            channel.control.set_bit(15, false);
+        }
+
+        if channel.control.bit(14) {
+            self.interrupts.borrow_mut().assert(interrupt(index));
+        }
+    }
+
+    fn write_control(&mut self, index: usize, value: u16) {
+        let channel = &mut self.channels[index];
+        let was_enabled = channel.is_enabled();
+        // Only channel 3 can be started by the game pak
+        channel.control = if index == 3 {
+            value & 0xFFE0
+        } else {
+            value & 0xF7E0
+        };
+
+        if !channel.is_enabled() {
+            channel.pending = false;
+        } else if !was_enabled {
+            channel.internal_source = source_mask(index, channel.source);
+            channel.internal_destination =
+                destination_mask(index, channel.destination);
+            channel.internal_count = transfer_count(index, channel.count);
+            channel.pending = channel.timing() == Timing::Immediate;
+        }
+    }
+}
+
+// Channel 0 can't read from the game pak
+fn source_mask(index: usize, address: u32) -> u32 {
+    if index == 0 {
+        address & 0x7FFFFFF
+    } else {
+        address & 0xFFFFFFF
+    }
+}
+
+// Only channel 3 can be written to the game pak
+fn destination_mask(index: usize, address: u32) -> u32 {
+    if index == 3 {
+        address & 0xFFFFFFF
+    } else {
+        address & 0x7FFFFFF
+    }
+}
+
+// A count of 0 transfers the maximum number of units
+fn transfer_count(index: usize, count: u16) -> u32 {
+    let max = if index == 3 { 0x10000 } else { 0x4000 };
+    match count as u32 & (max - 1) {
+        0 => max,
+        count => count,
+    }
+}
+
+fn interrupt(index: usize) -> Input {
+    match index {
+        0 => Input::Dma0,
+        1 => Input::Dma1,
+        2 => Input::Dma2,
+        _ => Input::Dma3,
+    }
+}
+
+// Each channel has 12 bytes of registers from 0xB0: SAD, DAD, CNT_L and
+// CNT_H. Only CNT_H can be read.
+impl Read for Dma {
+    fn read_byte(&self, address: u32) -> u8 {
+        let halfword = self.read_halfword(address & !1);
+        if address.bit(0) {
+            (halfword >> 8) as u8
+        } else {
+            halfword as u8
+        }
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        let index = (address as usize - 0xB0) / 12;
+        match (address - 0xB0) % 12 {
+            10 => self.channels[index].control,
+            _ => 0,
+        }
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        self.read_halfword(address) as u32
+            | (self.read_halfword(address + 2) as u32) << 16
+    }
+}
+
+impl Write for Dma {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        let index = (address as usize - 0xB0) / 12;
+        let channel = &self.channels[index];
+        let mut halfword = match ((address & !1) - 0xB0) % 12 {
+            0 => channel.source as u16,
+            2 => (channel.source >> 16) as u16,
+            4 => channel.destination as u16,
+            6 => (channel.destination >> 16) as u16,
+            8 => channel.count,
+            _ => channel.control,
+        };
+        if address.bit(0) {
+            halfword.set_bits(8..16, value as u16);
+        } else {
+            halfword.set_bits(0..8, value as u16);
+        }
+        self.write_halfword(address & !1, halfword);
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        let index = (address as usize - 0xB0) / 12;
+        let channel = &mut self.channels[index];
+        match (address - 0xB0) % 12 {
+            0 => channel.source.set_bits(0..16, value as u32),
+            2 => channel.source.set_bits(16..32, value as u32),
+            4 => channel.destination.set_bits(0..16, value as u32),
+            6 => channel.destination.set_bits(16..32, value as u32),
+            8 => channel.count = value,
+            _ => self.write_control(index, value),
+        }
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        self.write_halfword(address, value as u16);
+        self.write_halfword(address + 2, (value >> 16) as u16);
+    }
+}
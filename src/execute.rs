@@ -1,16 +1,17 @@
-use bit::{Bit, Bits, SetBit, SetBits};
+use bit::{Bit, Bits};
+use bios;
 use bus::{Read, Write};
-use cpu::{Cpu, LR, PC};
-use instruction::{AddressMode1, AddressMode2, AddressMode3, AddressingMode,
-                  AddressingOffset, Condition, Instruction, ShiftDirection};
-
-pub fn execute(cpu: &mut Cpu, inst: Instruction) {
-    if condition_passed(cpu, inst.condition()) {
-        println!("Executing {}", inst);
-    } else {
-        println!("Skipping {}", inst);
-        return;
+use cpu::{Cpu, Exception, Register, LR, PC, SP};
+use cycles::{self, Cycles};
+use instruction::{AddressMode1, AddressMode2, AddressMode3, AddressMode4,
+                  AddressingMode, AddressingOffset, Condition, Instruction,
+                  ShiftDirection, ThumbInstruction};
+
+pub fn execute(cpu: &mut Cpu, inst: Instruction) -> Cycles {
+    if !condition_passed(cpu, inst.condition()) {
+        return cycles::skipped();
     }
+    let cycles = cycles::arm(cpu, &inst);
 
     match inst {
         Instruction::B {
@@ -21,7 +22,8 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
                 cpu.registers[LR] = pc_val + 4;
             }
 
-            cpu.registers[PC] += sign_extend(signed_immed, 24) << 2;
+            let offset = sign_extend(signed_immed, 24) << 2;
+            cpu.registers[PC] = cpu.registers[PC].wrapping_add(offset);
         }
 
         Instruction::Bx { rm, .. } => {
@@ -43,7 +45,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -60,11 +62,11 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
         } => {
             let (shifter_operand, shifter_carry_out) =
                 addr_mode_1(cpu, operand2);
-            let result = cpu.registers[rn] | shifter_operand;
+            let result = cpu.registers[rn] ^ shifter_operand;
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -79,14 +81,14 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             operand2,
             ..
         } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
-            let result = rn_val - shifter_operand;
+            let result = rn_val.wrapping_sub(shifter_operand);
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -106,14 +108,14 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             operand2,
             ..
         } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
-            let result = shifter_operand - rn_val;
+            let result = shifter_operand.wrapping_sub(rn_val);
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -133,7 +135,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             operand2,
             ..
         } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
             let result_long = rn_val as u64 + shifter_operand as u64;
@@ -141,7 +143,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -161,7 +163,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             operand2,
             ..
         } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
             let c_flag = if cpu.cpsr.c() { 1 } else { 0 };
@@ -171,7 +173,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -191,25 +193,22 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             operand2,
             ..
         } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
             let not_c_flag = if cpu.cpsr.c() { 0 } else { 1 };
-            let result = rn_val - shifter_operand - not_c_flag;
+            let subtrahend = shifter_operand as u64 + not_c_flag;
+            let result = (rn_val as u64).wrapping_sub(subtrahend) as u32;
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
+                cpu.cpsr.set_c((rn_val as u64) >= subtrahend);
                 cpu.cpsr
-                    .set_c(!borrow_from(rn_val, shifter_operand + not_c_flag));
-                cpu.cpsr.set_v(overflow_from_sub(
-                    rn_val,
-                    shifter_operand + not_c_flag,
-                    result,
-                ));
+                    .set_v(overflow_from_sub(rn_val, shifter_operand, result));
             }
         }
 
@@ -220,25 +219,23 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             operand2,
             ..
         } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
             let not_c_flag = if cpu.cpsr.c() { 0 } else { 1 };
-            let result = shifter_operand - rn_val - not_c_flag;
+            let subtrahend = rn_val as u64 + not_c_flag;
+            let result =
+                (shifter_operand as u64).wrapping_sub(subtrahend) as u32;
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
+                cpu.cpsr.set_c((shifter_operand as u64) >= subtrahend);
                 cpu.cpsr
-                    .set_c(!borrow_from(shifter_operand, rn_val + not_c_flag));
-                cpu.cpsr.set_v(overflow_from_sub(
-                    shifter_operand,
-                    rn_val + not_c_flag,
-                    result,
-                ));
+                    .set_v(overflow_from_sub(shifter_operand, rn_val, result));
             }
         }
 
@@ -256,26 +253,26 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             let (shifter_operand, shifter_carry_out) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
-            let result = rn_val | shifter_operand;
+            let result = rn_val ^ shifter_operand;
             cpu.cpsr.set_n(result.bit(31));
             cpu.cpsr.set_z(result == 0);
             cpu.cpsr.set_c(shifter_carry_out);
         }
 
         Instruction::Cmp { rn, operand2, .. } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
-            let result = rn_val - shifter_operand;
+            let result = rn_val.wrapping_sub(shifter_operand);
             cpu.cpsr.set_n(result.bit(31));
             cpu.cpsr.set_z(result == 0);
             cpu.cpsr.set_c(!borrow_from(rn_val, shifter_operand));
             cpu.cpsr
-                .set_z(overflow_from_sub(rn_val, shifter_operand, result));
+                .set_v(overflow_from_sub(rn_val, shifter_operand, result));
         }
 
         Instruction::Cmn { rn, operand2, .. } => {
-            let (shifter_operand, shifter_carry_out) =
+            let (shifter_operand, _) =
                 addr_mode_1(cpu, operand2);
             let rn_val = cpu.registers[rn];
             let result_long = rn_val as u64 + shifter_operand as u64;
@@ -285,7 +282,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.cpsr.set_z(result == 0);
             cpu.cpsr.set_c(carry_from(result_long));
             cpu.cpsr
-                .set_z(overflow_from_add(rn_val, shifter_operand, result));
+                .set_v(overflow_from_add(rn_val, shifter_operand, result));
         }
 
         Instruction::Orr {
@@ -302,7 +299,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -318,7 +315,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = shifter_operand;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(shifter_operand.bit(31));
                 cpu.cpsr.set_z(shifter_operand == 0);
@@ -340,7 +337,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -357,7 +354,7 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.registers[rd] = result;
 
             if s && rd == PC {
-                cpu.cpsr = cpu.spsr;
+                cpu.restore_cpsr();
             } else if s {
                 cpu.cpsr.set_n(result.bit(31));
                 cpu.cpsr.set_z(result == 0);
@@ -365,57 +362,27 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             }
         }
 
-        Instruction::Mul { s, rd, rm, rs, .. } => {
+        Instruction::Mul { .. } => {
             unimplemented!();
         }
 
-        Instruction::Mla {
-            s, rd, rn, rm, rs, ..
-        } => {
+        Instruction::Mla { .. } => {
             unimplemented!();
         }
 
-        Instruction::Umull {
-            s,
-            rd_hi,
-            rd_lo,
-            rm,
-            rs,
-            ..
-        } => {
+        Instruction::Umull { .. } => {
             unimplemented!();
         }
 
-        Instruction::Umlal {
-            s,
-            rd_hi,
-            rd_lo,
-            rm,
-            rs,
-            ..
-        } => {
+        Instruction::Umlal { .. } => {
             unimplemented!();
         }
 
-        Instruction::Smull {
-            s,
-            rd_hi,
-            rd_lo,
-            rm,
-            rs,
-            ..
-        } => {
+        Instruction::Smull { .. } => {
             unimplemented!();
         }
 
-        Instruction::Smlal {
-            s,
-            rd_hi,
-            rd_lo,
-            rm,
-            rs,
-            ..
-        } => {
+        Instruction::Smlal { .. } => {
             unimplemented!();
         }
 
@@ -431,70 +398,87 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             let (operand, _) = addr_mode_1(cpu, address);
             if r {
                 if !cpu.cpsr.has_spsr() {
-                    return;
+                    return cycles;
                 }
+                let mut spsr = cpu.spsr();
                 if c {
-                    cpu.spsr.set_bits(0..8, operand.bits(0..8));
+                    spsr.set_bits(0..8, operand.bits(0..8));
                 }
                 if x {
-                    cpu.spsr.set_bits(8..16, operand.bits(8..16));
+                    spsr.set_bits(8..16, operand.bits(8..16));
                 }
                 if s {
-                    cpu.spsr.set_bits(16..24, operand.bits(16..24));
+                    spsr.set_bits(16..24, operand.bits(16..24));
                 }
                 if f {
-                    cpu.spsr.set_bits(24..32, operand.bits(24..32));
+                    spsr.set_bits(24..32, operand.bits(24..32));
                 }
+                cpu.set_spsr(spsr);
             } else {
                 let priviledged = cpu.cpsr.is_priviledged();
+                let mut cpsr = cpu.cpsr;
                 if c && priviledged {
-                    cpu.cpsr.set_bits(0..8, operand.bits(0..8));
+                    cpsr.set_bits(0..8, operand.bits(0..8));
                 }
                 if x && priviledged {
-                    cpu.cpsr.set_bits(8..16, operand.bits(8..16));
+                    cpsr.set_bits(8..16, operand.bits(8..16));
                 }
                 if s && priviledged {
-                    cpu.cpsr.set_bits(16..24, operand.bits(16..24));
+                    cpsr.set_bits(16..24, operand.bits(16..24));
                 }
                 if f {
-                    cpu.cpsr.set_bits(24..32, operand.bits(24..32));
+                    cpsr.set_bits(24..32, operand.bits(24..32));
                 }
+                cpu.set_cpsr(cpsr);
             }
         }
 
         Instruction::Mrs { r, rd, .. } => {
             cpu.registers[rd] = if r {
-                cpu.spsr.to_bits()
+                cpu.spsr().to_bits()
             } else {
                 cpu.cpsr.to_bits()
             };
         }
 
         Instruction::Ldrh { rd, address, .. } => {
-            unimplemented!();
+            let address = addr_mode_3(cpu, address);
+            cpu.registers[rd] = read_halfword_rotated(cpu, address);
         }
 
         Instruction::Ldrsb { rd, address, .. } => {
-            unimplemented!();
+            let address = addr_mode_3(cpu, address);
+            let value = cpu.memory.read_byte(address) as u32;
+            cpu.registers[rd] = sign_extend(value, 8);
         }
 
         Instruction::Ldrsh { rd, address, .. } => {
-            unimplemented!();
+            let address = addr_mode_3(cpu, address);
+            cpu.registers[rd] = read_halfword_signed(cpu, address);
         }
 
         Instruction::Strh { rd, address, .. } => {
-            unimplemented!();
+            // Rd is read before writeback, so a base register being written
+            // back is stored with its original value. The PC is stored as the
+            // address of the instruction plus 12.
+            let value = if rd == PC {
+                cpu.registers[PC] + 4
+            } else {
+                cpu.registers[rd]
+            };
+            let address = addr_mode_3(cpu, address);
+            cpu.memory.write_halfword(address, value as u16);
         }
 
         Instruction::Ldrbt { rd, address, .. } => {
             let address = addr_mode_2(cpu, address);
-            // TODO: signal memory system to act as if CPU is in user mode
+            // There is no memory protection, so user mode transfers are made
+            // like any other
             cpu.registers[rd] = cpu.memory.read_byte(address) as u32;
         }
 
         Instruction::Ldrt { rd, address, .. } => {
             let address = addr_mode_2(cpu, address);
-            // TODO: signal memory system to act as if CPU is in user mode
             let value = cpu.memory.read_word(address);
             let rotation = address.bits(0..2);
             cpu.registers[rd] = value.rotate_right(8 * rotation);
@@ -520,13 +504,11 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
 
         Instruction::Strbt { rd, address, .. } => {
             let address = addr_mode_2(cpu, address);
-            // TODO: signal memory system to act as if CPU is in user mode
             cpu.memory.write_byte(address, cpu.registers[rd] as u8);
         }
 
         Instruction::Strt { rd, address, .. } => {
             let address = addr_mode_2(cpu, address);
-            // TODO: signal memory system to act as if CPU is in user mode
             cpu.memory.write_word(address, cpu.registers[rd]);
         }
 
@@ -540,58 +522,581 @@ pub fn execute(cpu: &mut Cpu, inst: Instruction) {
             cpu.memory.write_word(address, cpu.registers[rd]);
         }
 
-        Instruction::Ldm1 { .. } => {
-            unimplemented!();
+        Instruction::Ldm1 {
+            address,
+            register_list,
+            ..
+        } => {
+            let (start_address, rn_val) =
+                addr_mode_4(cpu, address, register_list);
+            if address.w {
+                cpu.registers[address.rn] = rn_val;
+            }
+
+            let mut address = start_address;
+            for i in 0..16 {
+                if transfer_list(register_list).bit(i) {
+                    let value = cpu.memory.read_word(address);
+                    cpu.registers[Register(i as u32)] = if i == 15 {
+                        value & 0xFFFFFFFC
+                    } else {
+                        value
+                    };
+                    address += 4;
+                }
+            }
         }
 
-        Instruction::Ldm2 { .. } => {
-            unimplemented!();
+        Instruction::Ldm2 {
+            address,
+            register_list,
+            ..
+        } => {
+            let (start_address, _) = addr_mode_4(cpu, address, register_list);
+
+            let mut address = start_address;
+            for i in 0..15 {
+                if register_list.bit(i) {
+                    let value = cpu.memory.read_word(address);
+                    cpu.registers.set_user(Register(i as u32), value);
+                    address += 4;
+                }
+            }
         }
 
-        Instruction::Ldm3 { .. } => {
-            unimplemented!();
+        Instruction::Ldm3 {
+            address,
+            register_list,
+            ..
+        } => {
+            let (start_address, rn_val) =
+                addr_mode_4(cpu, address, register_list);
+            if address.w {
+                cpu.registers[address.rn] = rn_val;
+            }
+
+            let mut address = start_address;
+            for i in 0..15 {
+                if register_list.bit(i) {
+                    cpu.registers[Register(i as u32)] =
+                        cpu.memory.read_word(address);
+                    address += 4;
+                }
+            }
+
+            cpu.restore_cpsr();
+            let value = cpu.memory.read_word(address);
+            cpu.registers[PC] = if cpu.cpsr.t() {
+                value & 0xFFFFFFFE
+            } else {
+                value & 0xFFFFFFFC
+            };
         }
 
-        Instruction::Stm1 { .. } => {
-            unimplemented!();
+        Instruction::Stm1 {
+            address,
+            register_list,
+            ..
+        } => {
+            let (start_address, rn_val) =
+                addr_mode_4(cpu, address, register_list);
+
+            // The base register is written back after the first transfer,
+            // so a base in the list is stored unchanged only if it is the
+            // lowest numbered register.
+            let mut address_val = start_address;
+            let mut first = true;
+            for i in 0..16 {
+                if transfer_list(register_list).bit(i) {
+                    let value = if i == 15 {
+                        cpu.registers[PC] + 4
+                    } else {
+                        cpu.registers[Register(i as u32)]
+                    };
+                    cpu.memory.write_word(address_val, value);
+                    address_val += 4;
+
+                    if first && address.w {
+                        cpu.registers[address.rn] = rn_val;
+                    }
+                    first = false;
+                }
+            }
         }
 
-        Instruction::Stm2 { .. } => {
-            unimplemented!();
+        Instruction::Stm2 {
+            address,
+            register_list,
+            ..
+        } => {
+            let (start_address, _) = addr_mode_4(cpu, address, register_list);
+
+            let mut address = start_address;
+            for i in 0..16 {
+                if transfer_list(register_list).bit(i) {
+                    let value = if i == 15 {
+                        cpu.registers[PC] + 4
+                    } else {
+                        cpu.registers.user(Register(i as u32))
+                    };
+                    cpu.memory.write_word(address, value);
+                    address += 4;
+                }
+            }
         }
 
+        // The read and write are performed as a locked pair of bus accesses
         Instruction::Swpb { rd, rm, rn, .. } => {
-            unimplemented!();
+            let address = cpu.registers[rn];
+            let value = cpu.memory.read_byte(address);
+            cpu.memory.write_byte(address, cpu.registers[rm] as u8);
+            cpu.registers[rd] = value as u32;
         }
 
         Instruction::Swp { rd, rm, rn, .. } => {
-            unimplemented!();
+            let address = cpu.registers[rn];
+            let value = read_word_rotated(cpu, address);
+            cpu.memory.write_word(address, cpu.registers[rm]);
+            cpu.registers[rd] = value;
         }
 
         Instruction::Swi { immediate, .. } => {
-            unimplemented!();
+            // The BIOS takes the function number from the top byte of the
+            // comment field
+            if cpu.hle.is_some() {
+                bios::call(cpu, immediate.bits(16..24));
+            } else {
+                cpu.exception(Exception::SoftwareInterrupt);
+            }
         }
 
-        Instruction::Cdp { .. } => {
-            unimplemented!();
+        Instruction::Undefined { .. } => {
+            cpu.exception(Exception::Undefined);
         }
 
-        Instruction::Ldc { .. } => {
-            unimplemented!();
+        // There are no coprocessors, so coprocessor instructions are
+        // undefined
+        Instruction::Cdp { .. }
+        | Instruction::Ldc { .. }
+        | Instruction::Mcr { .. }
+        | Instruction::Mrc { .. }
+        | Instruction::Stc { .. } => {
+            cpu.exception(Exception::Undefined);
         }
+    };
+    cycles
+}
 
-        Instruction::Mcr { .. } => {
-            unimplemented!();
+pub fn execute_thumb(cpu: &mut Cpu, inst: ThumbInstruction) -> Cycles {
+    let cycles = cycles::thumb(cpu, &inst);
+
+    match inst {
+        ThumbInstruction::B1 {
+            condition,
+            signed_immed_8,
+        } => {
+            if condition_passed(cpu, condition) {
+                let offset = sign_extend(signed_immed_8, 8) << 1;
+                cpu.registers[PC] = cpu.registers[PC].wrapping_add(offset);
+            }
         }
 
-        Instruction::Mrc { .. } => {
-            unimplemented!();
+        ThumbInstruction::B2 { signed_immed_11 } => {
+            let offset = sign_extend(signed_immed_11, 11) << 1;
+            cpu.registers[PC] = cpu.registers[PC].wrapping_add(offset);
         }
 
-        Instruction::Stc { .. } => {
-            unimplemented!();
+        ThumbInstruction::Bl { h, offset_11 } => {
+            let pc_val = cpu.registers[PC];
+            if h == 0b10 {
+                let offset = sign_extend(offset_11, 11) << 12;
+                cpu.registers[LR] = pc_val.wrapping_add(offset);
+            } else {
+                let lr_val = cpu.registers[LR];
+                cpu.registers[PC] = lr_val.wrapping_add(offset_11 << 1);
+                // Address of the instruction following the BL suffix
+                cpu.registers[LR] = (pc_val - 2) | 1;
+            }
+        }
+
+        ThumbInstruction::Bx { rm } => {
+            let rm_val = cpu.registers[rm];
+            cpu.cpsr.set_t(rm_val.bit(0));
+            cpu.registers[PC] = rm_val & 0xFFFFFFFE;
+        }
+
+        ThumbInstruction::Adc { rd, rm } => {
+            let rd_val = cpu.registers[rd];
+            let rm_val = cpu.registers[rm];
+            let c_flag = cpu.cpsr.c() as u64;
+            let result_long = rd_val as u64 + rm_val as u64 + c_flag;
+            let result = result_long as u32;
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+            cpu.cpsr.set_c(carry_from(result_long));
+            cpu.cpsr.set_v(overflow_from_add(rd_val, rm_val, result));
+        }
+
+        ThumbInstruction::Add1 { rd, rn, immed_3 } => {
+            let rn_val = cpu.registers[rn];
+            let result = thumb_add(cpu, rn_val, immed_3);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Add2 { rd, immed_8 } => {
+            let rd_val = cpu.registers[rd];
+            let result = thumb_add(cpu, rd_val, immed_8);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Add3 { rd, rn, rm } => {
+            let rn_val = cpu.registers[rn];
+            let rm_val = cpu.registers[rm];
+            let result = thumb_add(cpu, rn_val, rm_val);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Add4 { rd, rm } => {
+            let result = cpu.registers[rd].wrapping_add(cpu.registers[rm]);
+            cpu.registers[rd] = if rd == PC {
+                result & 0xFFFFFFFE
+            } else {
+                result
+            };
+        }
+
+        ThumbInstruction::Add5 { rd, immed_8 } => {
+            cpu.registers[rd] = (cpu.registers[PC] & 0xFFFFFFFC) + immed_8 * 4;
+        }
+
+        ThumbInstruction::Add6 { rd, immed_8 } => {
+            cpu.registers[rd] = cpu.registers[SP].wrapping_add(immed_8 * 4);
+        }
+
+        ThumbInstruction::Add7 { immed_7 } => {
+            cpu.registers[SP] = cpu.registers[SP].wrapping_add(immed_7 * 4);
+        }
+
+        ThumbInstruction::And { rd, rm } => {
+            let result = cpu.registers[rd] & cpu.registers[rm];
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Asr1 { rd, rm, immed_5 } => {
+            thumb_shift_immediate(cpu, ShiftDirection::Asr, rd, rm, immed_5);
+        }
+
+        ThumbInstruction::Asr2 { rd, rs } => {
+            thumb_shift_register(cpu, ShiftDirection::Asr, rd, rs);
+        }
+
+        ThumbInstruction::Bic { rd, rm } => {
+            let result = cpu.registers[rd] & !cpu.registers[rm];
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Cmn { rn, rm } => {
+            let rn_val = cpu.registers[rn];
+            let rm_val = cpu.registers[rm];
+            thumb_add(cpu, rn_val, rm_val);
+        }
+
+        ThumbInstruction::Cmp1 { rn, immed_8 } => {
+            let rn_val = cpu.registers[rn];
+            thumb_sub(cpu, rn_val, immed_8);
+        }
+
+        ThumbInstruction::Cmp2 { rn, rm }
+        | ThumbInstruction::Cmp3 { rn, rm } => {
+            let rn_val = cpu.registers[rn];
+            let rm_val = cpu.registers[rm];
+            thumb_sub(cpu, rn_val, rm_val);
+        }
+
+        ThumbInstruction::Eor { rd, rm } => {
+            let result = cpu.registers[rd] ^ cpu.registers[rm];
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Lsl1 { rd, rm, immed_5 } => {
+            thumb_shift_immediate(cpu, ShiftDirection::Lsl, rd, rm, immed_5);
+        }
+
+        ThumbInstruction::Lsl2 { rd, rs } => {
+            thumb_shift_register(cpu, ShiftDirection::Lsl, rd, rs);
+        }
+
+        ThumbInstruction::Lsr1 { rd, rm, immed_5 } => {
+            thumb_shift_immediate(cpu, ShiftDirection::Lsr, rd, rm, immed_5);
+        }
+
+        ThumbInstruction::Lsr2 { rd, rs } => {
+            thumb_shift_register(cpu, ShiftDirection::Lsr, rd, rs);
+        }
+
+        ThumbInstruction::Mov1 { rd, immed_8 } => {
+            cpu.registers[rd] = immed_8;
+            cpu.cpsr.set_n(false);
+            cpu.cpsr.set_z(immed_8 == 0);
+        }
+
+        ThumbInstruction::Mov2 { rd, rn } => {
+            let result = cpu.registers[rn];
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+            cpu.cpsr.set_c(false);
+            cpu.cpsr.set_v(false);
+        }
+
+        ThumbInstruction::Mov3 { rd, rm } => {
+            let result = cpu.registers[rm];
+            cpu.registers[rd] = if rd == PC {
+                result & 0xFFFFFFFE
+            } else {
+                result
+            };
+        }
+
+        ThumbInstruction::Mul { rd, rm } => {
+            let result = cpu.registers[rm].wrapping_mul(cpu.registers[rd]);
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Mvn { rd, rm } => {
+            let result = !cpu.registers[rm];
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Neg { rd, rm } => {
+            let rm_val = cpu.registers[rm];
+            let result = thumb_sub(cpu, 0, rm_val);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Orr { rd, rm } => {
+            let result = cpu.registers[rd] | cpu.registers[rm];
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Ror { rd, rs } => {
+            thumb_shift_register(cpu, ShiftDirection::Ror, rd, rs);
+        }
+
+        ThumbInstruction::Sbc { rd, rm } => {
+            let rd_val = cpu.registers[rd];
+            let rm_val = cpu.registers[rm];
+            let not_c_flag = if cpu.cpsr.c() { 0 } else { 1 };
+            let subtrahend = rm_val as u64 + not_c_flag;
+            let result = (rd_val as u64).wrapping_sub(subtrahend) as u32;
+            cpu.registers[rd] = result;
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+            cpu.cpsr.set_c((rd_val as u64) >= subtrahend);
+            cpu.cpsr.set_v(overflow_from_sub(rd_val, rm_val, result));
+        }
+
+        ThumbInstruction::Sub1 { rd, rn, immed_3 } => {
+            let rn_val = cpu.registers[rn];
+            let result = thumb_sub(cpu, rn_val, immed_3);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Sub2 { rd, immed_8 } => {
+            let rd_val = cpu.registers[rd];
+            let result = thumb_sub(cpu, rd_val, immed_8);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Sub3 { rd, rn, rm } => {
+            let rn_val = cpu.registers[rn];
+            let rm_val = cpu.registers[rm];
+            let result = thumb_sub(cpu, rn_val, rm_val);
+            cpu.registers[rd] = result;
+        }
+
+        ThumbInstruction::Sub4 { immed_7 } => {
+            cpu.registers[SP] = cpu.registers[SP].wrapping_sub(immed_7 * 4);
+        }
+
+        ThumbInstruction::Tst { rn, rm } => {
+            let result = cpu.registers[rn] & cpu.registers[rm];
+            cpu.cpsr.set_n(result.bit(31));
+            cpu.cpsr.set_z(result == 0);
+        }
+
+        ThumbInstruction::Ldr1 { rd, rn, immed_5 } => {
+            let address = cpu.registers[rn].wrapping_add(immed_5 * 4);
+            cpu.registers[rd] = read_word_rotated(cpu, address);
+        }
+
+        ThumbInstruction::Ldr2 { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.registers[rd] = read_word_rotated(cpu, address);
+        }
+
+        ThumbInstruction::Ldr3 { rd, immed_8 } => {
+            let address = (cpu.registers[PC] & 0xFFFFFFFC) + immed_8 * 4;
+            cpu.registers[rd] = cpu.memory.read_word(address);
+        }
+
+        ThumbInstruction::Ldr4 { rd, immed_8 } => {
+            let address = cpu.registers[SP].wrapping_add(immed_8 * 4);
+            cpu.registers[rd] = read_word_rotated(cpu, address);
+        }
+
+        ThumbInstruction::Ldrb1 { rd, rn, immed_5 } => {
+            let address = cpu.registers[rn].wrapping_add(immed_5);
+            cpu.registers[rd] = cpu.memory.read_byte(address) as u32;
+        }
+
+        ThumbInstruction::Ldrb2 { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.registers[rd] = cpu.memory.read_byte(address) as u32;
+        }
+
+        ThumbInstruction::Ldrh1 { rd, rn, immed_5 } => {
+            let address = cpu.registers[rn].wrapping_add(immed_5 * 2);
+            cpu.registers[rd] = read_halfword_rotated(cpu, address);
+        }
+
+        ThumbInstruction::Ldrh2 { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.registers[rd] = read_halfword_rotated(cpu, address);
+        }
+
+        ThumbInstruction::Ldrsb { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            let value = cpu.memory.read_byte(address) as u32;
+            cpu.registers[rd] = sign_extend(value, 8);
+        }
+
+        ThumbInstruction::Ldrsh { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.registers[rd] = read_halfword_signed(cpu, address);
+        }
+
+        ThumbInstruction::Str1 { rd, rn, immed_5 } => {
+            let address = cpu.registers[rn].wrapping_add(immed_5 * 4);
+            cpu.memory.write_word(address, cpu.registers[rd]);
+        }
+
+        ThumbInstruction::Str2 { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.memory.write_word(address, cpu.registers[rd]);
+        }
+
+        ThumbInstruction::Str3 { rd, immed_8 } => {
+            let address = cpu.registers[SP].wrapping_add(immed_8 * 4);
+            cpu.memory.write_word(address, cpu.registers[rd]);
+        }
+
+        ThumbInstruction::Strb1 { rd, rn, immed_5 } => {
+            let address = cpu.registers[rn].wrapping_add(immed_5);
+            cpu.memory.write_byte(address, cpu.registers[rd] as u8);
+        }
+
+        ThumbInstruction::Strb2 { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.memory.write_byte(address, cpu.registers[rd] as u8);
+        }
+
+        ThumbInstruction::Strh1 { rd, rn, immed_5 } => {
+            let address = cpu.registers[rn].wrapping_add(immed_5 * 2);
+            cpu.memory.write_halfword(address, cpu.registers[rd] as u16);
+        }
+
+        ThumbInstruction::Strh2 { rd, rn, rm } => {
+            let address = cpu.registers[rn].wrapping_add(cpu.registers[rm]);
+            cpu.memory.write_halfword(address, cpu.registers[rd] as u16);
+        }
+
+        ThumbInstruction::Ldmia { rn, register_list } => {
+            let mut address = cpu.registers[rn];
+            for i in 0..8 {
+                if register_list.bit(i) {
+                    cpu.registers[Register(i as u32)] =
+                        cpu.memory.read_word(address);
+                    address = address.wrapping_add(4);
+                }
+            }
+            if !register_list.bit(rn.0 as u8) {
+                cpu.registers[rn] = address;
+            }
+        }
+
+        ThumbInstruction::Pop { r, register_list } => {
+            let mut address = cpu.registers[SP];
+            for i in 0..8 {
+                if register_list.bit(i) {
+                    cpu.registers[Register(i as u32)] =
+                        cpu.memory.read_word(address);
+                    address = address.wrapping_add(4);
+                }
+            }
+            if r {
+                cpu.registers[PC] = cpu.memory.read_word(address) & 0xFFFFFFFE;
+                address = address.wrapping_add(4);
+            }
+            cpu.registers[SP] = address;
+        }
+
+        ThumbInstruction::Push { r, register_list } => {
+            let count = register_list.count_ones() + r as u32;
+            let start_address = cpu.registers[SP].wrapping_sub(4 * count);
+            let mut address = start_address;
+            for i in 0..8 {
+                if register_list.bit(i) {
+                    let value = cpu.registers[Register(i as u32)];
+                    cpu.memory.write_word(address, value);
+                    address = address.wrapping_add(4);
+                }
+            }
+            if r {
+                let value = cpu.registers[LR];
+                cpu.memory.write_word(address, value);
+            }
+            cpu.registers[SP] = start_address;
+        }
+
+        ThumbInstruction::Stmia { rn, register_list } => {
+            let mut address = cpu.registers[rn];
+            for i in 0..8 {
+                if register_list.bit(i) {
+                    let value = cpu.registers[Register(i as u32)];
+                    cpu.memory.write_word(address, value);
+                    address = address.wrapping_add(4);
+                }
+            }
+            cpu.registers[rn] = address;
+        }
+
+        ThumbInstruction::Swi { immed_8 } => {
+            if cpu.hle.is_some() {
+                bios::call(cpu, immed_8);
+            } else {
+                cpu.exception(Exception::SoftwareInterrupt);
+            }
+        }
+
+        ThumbInstruction::Undefined { .. } => {
+            cpu.exception(Exception::Undefined);
         }
     };
+    cycles
 }
 
 fn condition_passed(cpu: &Cpu, condition: Condition) -> bool {
@@ -643,6 +1148,13 @@ fn addr_mode_1(cpu: &Cpu, address: AddressMode1) -> (u32, bool) {
         } => {
             let rm_val = cpu.registers[rm];
             let shift_imm = match shift_imm {
+                // LSR #0 and ASR #0 are used to encode shifts of 32
+                AddressingOffset::Immediate(0)
+                    if shift == ShiftDirection::Lsr
+                        || shift == ShiftDirection::Asr =>
+                {
+                    32
+                }
                 AddressingOffset::Immediate(value) => value,
                 AddressingOffset::Register(rs) => {
                     cpu.registers[rs].bits(0..8) as u16
@@ -701,7 +1213,7 @@ fn addr_mode_1(cpu: &Cpu, address: AddressMode1) -> (u32, bool) {
                     }
                 }
                 ShiftDirection::Ror => {
-                    let shift_imm2 = shift_imm.bits(0..4);
+                    let shift_imm2 = shift_imm.bits(0..5);
 
                     if shift_imm == 0 {
                         shifter_operand = rm_val;
@@ -776,9 +1288,9 @@ fn addr_mode_2(cpu: &mut Cpu, address: AddressMode2) -> u32 {
 
     let rn_val = cpu.registers[rn];
     let value = if u {
-        rn_val + offset_val
+        rn_val.wrapping_add(offset_val)
     } else {
-        rn_val - offset_val
+        rn_val.wrapping_sub(offset_val)
     };
 
     match addressing {
@@ -808,13 +1320,16 @@ fn addr_mode_3(cpu: &mut Cpu, address: AddressMode3) -> u32 {
         AddressingOffset::ScaledRegister { .. } => unreachable!(),
     };
 
+    // With the PC as Rn this is the address of the instruction plus 8
     let rn_val = cpu.registers[rn];
     let value = if u {
-        rn_val + offset_val
+        rn_val.wrapping_add(offset_val)
     } else {
-        rn_val - offset_val
+        rn_val.wrapping_sub(offset_val)
     };
 
+    // Writeback happens before a load, so if Rd is also Rn the loaded value
+    // takes precedence.
     match addressing {
         AddressingMode::Offset => value,
         AddressingMode::PreIndexed => {
@@ -828,6 +1343,139 @@ fn addr_mode_3(cpu: &mut Cpu, address: AddressMode3) -> u32 {
     }
 }
 
+// Returns (start_address, rn_val) where `rn_val` is the value Rn holds after
+// writeback.
+fn addr_mode_4(
+    cpu: &Cpu,
+    address: AddressMode4,
+    register_list: u32,
+) -> (u32, u32) {
+    let AddressMode4 { rn, p, u, .. } = address;
+    let rn_val = cpu.registers[rn];
+    // An empty register list transfers the PC but moves the base register
+    // as if all 16 registers had been transferred.
+    let count = if register_list == 0 {
+        16
+    } else {
+        register_list.count_ones()
+    };
+    let offset = 4 * count;
+
+    match (p, u) {
+        // Increment after
+        (false, true) => (rn_val, rn_val.wrapping_add(offset)),
+        // Increment before
+        (true, true) => (rn_val.wrapping_add(4), rn_val.wrapping_add(offset)),
+        // Decrement after
+        (false, false) => (
+            rn_val.wrapping_sub(offset).wrapping_add(4),
+            rn_val.wrapping_sub(offset),
+        ),
+        // Decrement before
+        (true, false) => {
+            (rn_val.wrapping_sub(offset), rn_val.wrapping_sub(offset))
+        }
+    }
+}
+
+// The ARM7TDMI transfers only the PC when given an empty register list.
+fn transfer_list(register_list: u32) -> u32 {
+    if register_list == 0 {
+        1 << 15
+    } else {
+        register_list
+    }
+}
+
+// Thumb helpers
+
+// Adds two operands, setting the N, Z, C and V flags from the result.
+fn thumb_add(cpu: &mut Cpu, operand1: u32, operand2: u32) -> u32 {
+    let result_long = operand1 as u64 + operand2 as u64;
+    let result = result_long as u32;
+    cpu.cpsr.set_n(result.bit(31));
+    cpu.cpsr.set_z(result == 0);
+    cpu.cpsr.set_c(carry_from(result_long));
+    cpu.cpsr
+        .set_v(overflow_from_add(operand1, operand2, result));
+    result
+}
+
+// Subtracts two operands, setting the N, Z, C and V flags from the result.
+fn thumb_sub(cpu: &mut Cpu, operand1: u32, operand2: u32) -> u32 {
+    let result = operand1.wrapping_sub(operand2);
+    cpu.cpsr.set_n(result.bit(31));
+    cpu.cpsr.set_z(result == 0);
+    cpu.cpsr.set_c(!borrow_from(operand1, operand2));
+    cpu.cpsr
+        .set_v(overflow_from_sub(operand1, operand2, result));
+    result
+}
+
+// Thumb shifts behave like the equivalent ARM `MOVS <Rd>, <Rm>, <shift>` so
+// are computed with the address mode 1 shifter.
+fn thumb_shift_immediate(
+    cpu: &mut Cpu,
+    shift: ShiftDirection,
+    rd: Register,
+    rm: Register,
+    immed_5: u32,
+) {
+    let operand = AddressMode1::Shift {
+        rm: rm,
+        shift: shift,
+        shift_imm: AddressingOffset::Immediate(immed_5 as u16),
+    };
+    let (result, carry) = addr_mode_1(cpu, operand);
+    cpu.registers[rd] = result;
+    cpu.cpsr.set_n(result.bit(31));
+    cpu.cpsr.set_z(result == 0);
+    cpu.cpsr.set_c(carry);
+}
+
+fn thumb_shift_register(
+    cpu: &mut Cpu,
+    shift: ShiftDirection,
+    rd: Register,
+    rs: Register,
+) {
+    let operand = AddressMode1::Shift {
+        rm: rd,
+        shift: shift,
+        shift_imm: AddressingOffset::Register(rs),
+    };
+    let (result, carry) = addr_mode_1(cpu, operand);
+    cpu.registers[rd] = result;
+    cpu.cpsr.set_n(result.bit(31));
+    cpu.cpsr.set_z(result == 0);
+    cpu.cpsr.set_c(carry);
+}
+
+// Reads a word, rotating the result when the address is not word aligned.
+fn read_word_rotated(cpu: &Cpu, address: u32) -> u32 {
+    let value = cpu.memory.read_word(address);
+    let rotation = address.bits(0..2);
+    value.rotate_right(8 * rotation)
+}
+
+// Reads a halfword, rotating the result when the address is not halfword
+// aligned.
+fn read_halfword_rotated(cpu: &Cpu, address: u32) -> u32 {
+    let value = cpu.memory.read_halfword(address) as u32;
+    let rotation = address.bits(0..1);
+    value.rotate_right(8 * rotation)
+}
+
+// Reads a sign extended halfword. A misaligned address reads the sign
+// extended byte instead.
+fn read_halfword_signed(cpu: &Cpu, address: u32) -> u32 {
+    if address.bit(0) {
+        sign_extend(cpu.memory.read_byte(address) as u32, 8)
+    } else {
+        sign_extend(cpu.memory.read_halfword(address) as u32, 16)
+    }
+}
+
 // Arithmetic flags
 
 fn carry_from(result_long: u64) -> bool {
@@ -1,3 +1,4 @@
+use bit::Bit;
 use cpu::Register;
 use std::fmt;
 
@@ -256,19 +257,29 @@ pub enum Instruction {
     // LoadAndStoreMultiple
     Ldm1 {
         condition: Condition,
-    }, // TODO
+        address: AddressMode4,
+        register_list: u32,
+    },
     Ldm2 {
         condition: Condition,
-    }, // TODO
+        address: AddressMode4,
+        register_list: u32,
+    },
     Ldm3 {
         condition: Condition,
-    }, // TODO
+        address: AddressMode4,
+        register_list: u32,
+    },
     Stm1 {
         condition: Condition,
-    }, // TODO
+        address: AddressMode4,
+        register_list: u32,
+    },
     Stm2 {
         condition: Condition,
-    }, // TODO
+        address: AddressMode4,
+        register_list: u32,
+    },
 
     // Semaphore
     Swpb {
@@ -289,23 +300,55 @@ pub enum Instruction {
         condition: Condition,
         immediate: u32,
     },
+    // Encodings with no instruction on the ARM7TDMI, which raise the
+    // Undefined Instruction exception
+    Undefined {
+        condition: Condition,
+        inst: u32,
+    },
 
     // Coprocessor
     Cdp {
         condition: Condition,
-    }, // TODO
+        cp_num: u32,
+        opcode_1: u32,
+        crd: u32,
+        crn: u32,
+        crm: u32,
+        opcode_2: u32,
+    },
     Ldc {
         condition: Condition,
-    }, // TODO
+        n: bool,
+        cp_num: u32,
+        crd: u32,
+        address: AddressMode5,
+    },
     Mcr {
         condition: Condition,
-    }, // TODO
+        cp_num: u32,
+        opcode_1: u32,
+        rd: Register,
+        crn: u32,
+        crm: u32,
+        opcode_2: u32,
+    },
     Mrc {
         condition: Condition,
-    }, // TODO
+        cp_num: u32,
+        opcode_1: u32,
+        rd: Register,
+        crn: u32,
+        crm: u32,
+        opcode_2: u32,
+    },
     Stc {
         condition: Condition,
-    }, // TODO
+        n: bool,
+        cp_num: u32,
+        crd: u32,
+        address: AddressMode5,
+    },
 }
 
 impl Instruction {
@@ -357,6 +400,7 @@ impl Instruction {
             | Instruction::Swpb { condition, .. }
             | Instruction::Swp { condition, .. }
             | Instruction::Swi { condition, .. }
+            | Instruction::Undefined { condition, .. }
             | Instruction::Cdp { condition, .. }
             | Instruction::Ldc { condition, .. }
             | Instruction::Mcr { condition, .. }
@@ -435,6 +479,23 @@ pub struct AddressMode3 {
     pub u: bool,
 }
 
+#[derive(Copy, Clone, PartialEq)]
+pub struct AddressMode4 {
+    pub rn: Register,
+    pub p: bool,
+    pub u: bool,
+    pub w: bool,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+pub struct AddressMode5 {
+    pub rn: Register,
+    pub offset_8: u32,
+    pub p: bool,
+    pub u: bool,
+    pub w: bool,
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum AddressingMode {
     Offset,
@@ -442,6 +503,284 @@ pub enum AddressingMode {
     PostIndexed,
 }
 
+#[derive(Copy, Clone)]
+pub enum ThumbInstruction {
+    // Branch
+    B1 {
+        condition: Condition,
+        signed_immed_8: u32,
+    },
+    B2 {
+        signed_immed_11: u32,
+    },
+    Bl {
+        h: u32,
+        offset_11: u32,
+    },
+    Bx {
+        rm: Register,
+    },
+
+    // Data processing
+    Adc {
+        rd: Register,
+        rm: Register,
+    },
+    Add1 {
+        rd: Register,
+        rn: Register,
+        immed_3: u32,
+    },
+    Add2 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Add3 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Add4 {
+        rd: Register,
+        rm: Register,
+    },
+    Add5 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Add6 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Add7 {
+        immed_7: u32,
+    },
+    And {
+        rd: Register,
+        rm: Register,
+    },
+    Asr1 {
+        rd: Register,
+        rm: Register,
+        immed_5: u32,
+    },
+    Asr2 {
+        rd: Register,
+        rs: Register,
+    },
+    Bic {
+        rd: Register,
+        rm: Register,
+    },
+    Cmn {
+        rn: Register,
+        rm: Register,
+    },
+    Cmp1 {
+        rn: Register,
+        immed_8: u32,
+    },
+    Cmp2 {
+        rn: Register,
+        rm: Register,
+    },
+    Cmp3 {
+        rn: Register,
+        rm: Register,
+    },
+    Eor {
+        rd: Register,
+        rm: Register,
+    },
+    Lsl1 {
+        rd: Register,
+        rm: Register,
+        immed_5: u32,
+    },
+    Lsl2 {
+        rd: Register,
+        rs: Register,
+    },
+    Lsr1 {
+        rd: Register,
+        rm: Register,
+        immed_5: u32,
+    },
+    Lsr2 {
+        rd: Register,
+        rs: Register,
+    },
+    Mov1 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Mov2 {
+        rd: Register,
+        rn: Register,
+    },
+    Mov3 {
+        rd: Register,
+        rm: Register,
+    },
+    Mul {
+        rd: Register,
+        rm: Register,
+    },
+    Mvn {
+        rd: Register,
+        rm: Register,
+    },
+    Neg {
+        rd: Register,
+        rm: Register,
+    },
+    Orr {
+        rd: Register,
+        rm: Register,
+    },
+    Ror {
+        rd: Register,
+        rs: Register,
+    },
+    Sbc {
+        rd: Register,
+        rm: Register,
+    },
+    Sub1 {
+        rd: Register,
+        rn: Register,
+        immed_3: u32,
+    },
+    Sub2 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Sub3 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Sub4 {
+        immed_7: u32,
+    },
+    Tst {
+        rn: Register,
+        rm: Register,
+    },
+
+    // Load/store register
+    Ldr1 {
+        rd: Register,
+        rn: Register,
+        immed_5: u32,
+    },
+    Ldr2 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Ldr3 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Ldr4 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Ldrb1 {
+        rd: Register,
+        rn: Register,
+        immed_5: u32,
+    },
+    Ldrb2 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Ldrh1 {
+        rd: Register,
+        rn: Register,
+        immed_5: u32,
+    },
+    Ldrh2 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Ldrsb {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Ldrsh {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Str1 {
+        rd: Register,
+        rn: Register,
+        immed_5: u32,
+    },
+    Str2 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Str3 {
+        rd: Register,
+        immed_8: u32,
+    },
+    Strb1 {
+        rd: Register,
+        rn: Register,
+        immed_5: u32,
+    },
+    Strb2 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+    Strh1 {
+        rd: Register,
+        rn: Register,
+        immed_5: u32,
+    },
+    Strh2 {
+        rd: Register,
+        rn: Register,
+        rm: Register,
+    },
+
+    // Load/store multiple
+    Ldmia {
+        rn: Register,
+        register_list: u32,
+    },
+    Pop {
+        r: bool,
+        register_list: u32,
+    },
+    Push {
+        r: bool,
+        register_list: u32,
+    },
+    Stmia {
+        rn: Register,
+        register_list: u32,
+    },
+
+    // Exception generating
+    Swi {
+        immed_8: u32,
+    },
+    // Encodings with no Thumb instruction on the ARM7TDMI, which raise the
+    // Undefined Instruction exception
+    Undefined {
+        inst: u32,
+    },
+}
+
 impl fmt::Display for Instruction {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -451,7 +790,8 @@ impl fmt::Display for Instruction {
                 signed_immed,
             } => {
                 // TODO: add PC
-                let value = ((((signed_immed as i32) << 8) >> 6) as u32) + 8;
+                let offset = (((signed_immed as i32) << 8) >> 6) as u32;
+                let value = offset.wrapping_add(8);
                 write!(f, "b{}{}\t{:#x}", format_bool(l, "l"), condition, value)
             }
 
@@ -877,43 +1217,447 @@ impl fmt::Display for Instruction {
                 ref address,
             } => write!(f, "str{}\t{}, {}", condition, rd, address),
 
-            Instruction::Ldm1 { condition, .. } => {
-                write!(f, "ldm1{}", condition)
+            Instruction::Ldm1 {
+                condition,
+                ref address,
+                register_list,
+            } => write!(
+                f,
+                "ldm{}{}\t{}, {}",
+                condition,
+                format_block_mode(address),
+                address,
+                format_register_list(register_list)
+            ),
+
+            Instruction::Ldm2 {
+                condition,
+                ref address,
+                register_list,
             }
+            | Instruction::Ldm3 {
+                condition,
+                ref address,
+                register_list,
+            } => write!(
+                f,
+                "ldm{}{}\t{}, {}^",
+                condition,
+                format_block_mode(address),
+                address,
+                format_register_list(register_list)
+            ),
+
+            Instruction::Stm1 {
+                condition,
+                ref address,
+                register_list,
+            } => write!(
+                f,
+                "stm{}{}\t{}, {}",
+                condition,
+                format_block_mode(address),
+                address,
+                format_register_list(register_list)
+            ),
+
+            Instruction::Stm2 {
+                condition,
+                ref address,
+                register_list,
+            } => write!(
+                f,
+                "stm{}{}\t{}, {}^",
+                condition,
+                format_block_mode(address),
+                address,
+                format_register_list(register_list)
+            ),
+
+            Instruction::Swpb {
+                condition,
+                rd,
+                rm,
+                rn,
+            } => write!(f, "swpb{}\t{}, {}, [{}]", condition, rd, rm, rn),
+
+            Instruction::Swp {
+                condition,
+                rd,
+                rm,
+                rn,
+            } => write!(f, "swp{}\t{}, {}, [{}]", condition, rd, rm, rn),
+
+            Instruction::Swi {
+                condition,
+                immediate,
+            } => write!(f, "swi{}\t{:#x}", condition, immediate),
 
-            Instruction::Ldm2 { condition, .. } => {
-                write!(f, "ldm2{}", condition)
+            Instruction::Undefined { inst, .. } => {
+                write!(f, "\t\t\t; <UNDEFINED> instruction: {:#010x}", inst)
             }
 
-            Instruction::Ldm3 { condition, .. } => {
-                write!(f, "ldm3{}", condition)
+            Instruction::Cdp {
+                condition,
+                cp_num,
+                opcode_1,
+                crd,
+                crn,
+                crm,
+                opcode_2,
+            } => write!(
+                f,
+                "cdp{}\tp{}, {}, c{}, c{}, c{}, {}",
+                condition, cp_num, opcode_1, crd, crn, crm, opcode_2
+            ),
+
+            Instruction::Ldc {
+                condition,
+                n,
+                cp_num,
+                crd,
+                ref address,
+            } => write!(
+                f,
+                "ldc{}{}\tp{}, c{}, {}",
+                condition,
+                format_bool(n, "l"),
+                cp_num,
+                crd,
+                address
+            ),
+
+            Instruction::Mcr {
+                condition,
+                cp_num,
+                opcode_1,
+                rd,
+                crn,
+                crm,
+                opcode_2,
+            } => write!(
+                f,
+                "mcr{}\tp{}, {}, {}, c{}, c{}, {}",
+                condition, cp_num, opcode_1, rd, crn, crm, opcode_2
+            ),
+
+            Instruction::Mrc {
+                condition,
+                cp_num,
+                opcode_1,
+                rd,
+                crn,
+                crm,
+                opcode_2,
+            } => write!(
+                f,
+                "mrc{}\tp{}, {}, {}, c{}, c{}, {}",
+                condition, cp_num, opcode_1, rd, crn, crm, opcode_2
+            ),
+
+            Instruction::Stc {
+                condition,
+                n,
+                cp_num,
+                crd,
+                ref address,
+            } => write!(
+                f,
+                "stc{}{}\tp{}, c{}, {}",
+                condition,
+                format_bool(n, "l"),
+                cp_num,
+                crd,
+                address
+            ),
+        }
+    }
+}
+
+impl fmt::Display for ThumbInstruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ThumbInstruction::B1 {
+                condition,
+                signed_immed_8,
+            } => {
+                // TODO: add PC
+                let value =
+                    ((((signed_immed_8 as i32) << 24) >> 23) as u32) + 4;
+                write!(f, "b{}\t{:#x}", condition, value)
             }
 
-            Instruction::Stm1 { condition, .. } => {
-                write!(f, "stm1{}", condition)
+            ThumbInstruction::B2 { signed_immed_11 } => {
+                // TODO: add PC
+                let value =
+                    ((((signed_immed_11 as i32) << 21) >> 20) as u32) + 4;
+                write!(f, "b\t{:#x}", value)
             }
 
-            Instruction::Stm2 { condition, .. } => {
-                write!(f, "stm2{}", condition)
+            ThumbInstruction::Bl { h, offset_11 } => {
+                if h == 0b10 {
+                    let value = (((offset_11 as i32) << 21) >> 9) as u32;
+                    write!(f, "bl\t{:#x}", value + 4)
+                } else {
+                    write!(f, "bl\tlr, #{:#x}", offset_11 << 1)
+                }
             }
 
-            Instruction::Swpb { condition, .. } => {
-                write!(f, "swpb{}", condition)
+            ThumbInstruction::Bx { rm } => write!(f, "bx\t{}", rm),
+
+            ThumbInstruction::Adc { rd, rm } => {
+                write!(f, "adcs\t{}, {}", rd, rm)
             }
 
-            Instruction::Swp { condition, .. } => write!(f, "swp{}", condition),
+            ThumbInstruction::Add1 { rd, rn, immed_3 } => {
+                write!(f, "adds\t{}, {}, #{}", rd, rn, immed_3)
+            }
 
-            Instruction::Swi { condition, .. } => write!(f, "swi{}", condition),
+            ThumbInstruction::Add2 { rd, immed_8 } => {
+                write!(f, "adds\t{}, #{}", rd, immed_8)
+            }
 
-            Instruction::Cdp { condition, .. } => write!(f, "cdp{}", condition),
+            ThumbInstruction::Add3 { rd, rn, rm } => {
+                write!(f, "adds\t{}, {}, {}", rd, rn, rm)
+            }
 
-            Instruction::Ldc { condition, .. } => write!(f, "ldc{}", condition),
+            ThumbInstruction::Add4 { rd, rm } => {
+                write!(f, "add\t{}, {}", rd, rm)
+            }
 
-            Instruction::Mcr { condition, .. } => write!(f, "mcr{}", condition),
+            ThumbInstruction::Add5 { rd, immed_8 } => {
+                write!(f, "add\t{}, pc, #{}", rd, immed_8 * 4)
+            }
+
+            ThumbInstruction::Add6 { rd, immed_8 } => {
+                write!(f, "add\t{}, sp, #{}", rd, immed_8 * 4)
+            }
+
+            ThumbInstruction::Add7 { immed_7 } => {
+                write!(f, "add\tsp, #{}", immed_7 * 4)
+            }
+
+            ThumbInstruction::And { rd, rm } => {
+                write!(f, "ands\t{}, {}", rd, rm)
+            }
 
-            Instruction::Mrc { condition, .. } => write!(f, "mrc{}", condition),
+            ThumbInstruction::Asr1 { rd, rm, immed_5 } => write!(
+                f,
+                "asrs\t{}, {}, #{}",
+                rd,
+                rm,
+                if immed_5 == 0 { 32 } else { immed_5 }
+            ),
 
-            Instruction::Stc { condition, .. } => write!(f, "stc{}", condition),
+            ThumbInstruction::Asr2 { rd, rs } => {
+                write!(f, "asrs\t{}, {}", rd, rs)
+            }
+
+            ThumbInstruction::Bic { rd, rm } => {
+                write!(f, "bics\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Cmn { rn, rm } => {
+                write!(f, "cmn\t{}, {}", rn, rm)
+            }
+
+            ThumbInstruction::Cmp1 { rn, immed_8 } => {
+                write!(f, "cmp\t{}, #{}", rn, immed_8)
+            }
+
+            ThumbInstruction::Cmp2 { rn, rm }
+            | ThumbInstruction::Cmp3 { rn, rm } => {
+                write!(f, "cmp\t{}, {}", rn, rm)
+            }
+
+            ThumbInstruction::Eor { rd, rm } => {
+                write!(f, "eors\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Lsl1 { rd, rm, immed_5 } => {
+                if immed_5 == 0 {
+                    write!(f, "movs\t{}, {}", rd, rm)
+                } else {
+                    write!(f, "lsls\t{}, {}, #{}", rd, rm, immed_5)
+                }
+            }
+
+            ThumbInstruction::Lsl2 { rd, rs } => {
+                write!(f, "lsls\t{}, {}", rd, rs)
+            }
+
+            ThumbInstruction::Lsr1 { rd, rm, immed_5 } => write!(
+                f,
+                "lsrs\t{}, {}, #{}",
+                rd,
+                rm,
+                if immed_5 == 0 { 32 } else { immed_5 }
+            ),
+
+            ThumbInstruction::Lsr2 { rd, rs } => {
+                write!(f, "lsrs\t{}, {}", rd, rs)
+            }
+
+            ThumbInstruction::Mov1 { rd, immed_8 } => {
+                write!(f, "movs\t{}, #{}", rd, immed_8)
+            }
+
+            ThumbInstruction::Mov2 { rd, rn } => {
+                write!(f, "adds\t{}, {}, #0", rd, rn)
+            }
+
+            ThumbInstruction::Mov3 { rd, rm } => {
+                if rd == Register(8) && rm == Register(8) {
+                    write!(f, "nop\t\t\t; (mov r8, r8)")
+                } else {
+                    write!(f, "mov\t{}, {}", rd, rm)
+                }
+            }
+
+            ThumbInstruction::Mul { rd, rm } => {
+                write!(f, "muls\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Mvn { rd, rm } => {
+                write!(f, "mvns\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Neg { rd, rm } => {
+                write!(f, "negs\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Orr { rd, rm } => {
+                write!(f, "orrs\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Ror { rd, rs } => {
+                write!(f, "rors\t{}, {}", rd, rs)
+            }
+
+            ThumbInstruction::Sbc { rd, rm } => {
+                write!(f, "sbcs\t{}, {}", rd, rm)
+            }
+
+            ThumbInstruction::Sub1 { rd, rn, immed_3 } => {
+                write!(f, "subs\t{}, {}, #{}", rd, rn, immed_3)
+            }
+
+            ThumbInstruction::Sub2 { rd, immed_8 } => {
+                write!(f, "subs\t{}, #{}", rd, immed_8)
+            }
+
+            ThumbInstruction::Sub3 { rd, rn, rm } => {
+                write!(f, "subs\t{}, {}, {}", rd, rn, rm)
+            }
+
+            ThumbInstruction::Sub4 { immed_7 } => {
+                write!(f, "sub\tsp, #{}", immed_7 * 4)
+            }
+
+            ThumbInstruction::Tst { rn, rm } => {
+                write!(f, "tst\t{}, {}", rn, rm)
+            }
+
+            ThumbInstruction::Ldr1 { rd, rn, immed_5 } => {
+                write!(f, "ldr\t{}, [{}, #{}]", rd, rn, immed_5 * 4)
+            }
+
+            ThumbInstruction::Ldr2 { rd, rn, rm } => {
+                write!(f, "ldr\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Ldr3 { rd, immed_8 } => {
+                write!(f, "ldr\t{}, [pc, #{}]", rd, immed_8 * 4)
+            }
+
+            ThumbInstruction::Ldr4 { rd, immed_8 } => {
+                write!(f, "ldr\t{}, [sp, #{}]", rd, immed_8 * 4)
+            }
+
+            ThumbInstruction::Ldrb1 { rd, rn, immed_5 } => {
+                write!(f, "ldrb\t{}, [{}, #{}]", rd, rn, immed_5)
+            }
+
+            ThumbInstruction::Ldrb2 { rd, rn, rm } => {
+                write!(f, "ldrb\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Ldrh1 { rd, rn, immed_5 } => {
+                write!(f, "ldrh\t{}, [{}, #{}]", rd, rn, immed_5 * 2)
+            }
+
+            ThumbInstruction::Ldrh2 { rd, rn, rm } => {
+                write!(f, "ldrh\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Ldrsb { rd, rn, rm } => {
+                write!(f, "ldrsb\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Ldrsh { rd, rn, rm } => {
+                write!(f, "ldrsh\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Str1 { rd, rn, immed_5 } => {
+                write!(f, "str\t{}, [{}, #{}]", rd, rn, immed_5 * 4)
+            }
+
+            ThumbInstruction::Str2 { rd, rn, rm } => {
+                write!(f, "str\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Str3 { rd, immed_8 } => {
+                write!(f, "str\t{}, [sp, #{}]", rd, immed_8 * 4)
+            }
+
+            ThumbInstruction::Strb1 { rd, rn, immed_5 } => {
+                write!(f, "strb\t{}, [{}, #{}]", rd, rn, immed_5)
+            }
+
+            ThumbInstruction::Strb2 { rd, rn, rm } => {
+                write!(f, "strb\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Strh1 { rd, rn, immed_5 } => {
+                write!(f, "strh\t{}, [{}, #{}]", rd, rn, immed_5 * 2)
+            }
+
+            ThumbInstruction::Strh2 { rd, rn, rm } => {
+                write!(f, "strh\t{}, [{}, {}]", rd, rn, rm)
+            }
+
+            ThumbInstruction::Ldmia { rn, register_list } => write!(
+                f,
+                "ldmia\t{}{}, {}",
+                rn,
+                format_bool(!register_list.bit(rn.0 as u8), "!"),
+                format_register_list(register_list)
+            ),
+
+            ThumbInstruction::Pop { r, register_list } => write!(
+                f,
+                "pop\t{}",
+                format_register_list(register_list | (r as u32) << 15)
+            ),
+
+            ThumbInstruction::Push { r, register_list } => write!(
+                f,
+                "push\t{}",
+                format_register_list(register_list | (r as u32) << 14)
+            ),
+
+            ThumbInstruction::Stmia { rn, register_list } => write!(
+                f,
+                "stmia\t{}!, {}",
+                rn,
+                format_register_list(register_list)
+            ),
+
+            ThumbInstruction::Swi { immed_8 } => write!(f, "swi\t{}", immed_8),
+
+            ThumbInstruction::Undefined { inst } => {
+                write!(f, "\t\t\t; <UNDEFINED> instruction: {:#06x}", inst)
+            }
         }
     }
 }
@@ -1012,6 +1756,29 @@ impl fmt::Display for AddressMode2 {
     }
 }
 
+impl fmt::Display for AddressMode5 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let AddressMode5 {
+            rn,
+            offset_8,
+            p,
+            u,
+            w,
+        } = *self;
+        let signed_offset =
+            format!("#{}{:#x}", format_bool(!u, "-"), offset_8 * 4);
+
+        match (p, w) {
+            (true, false) => write!(f, "[{}, {}]", rn, signed_offset),
+            (true, true) => write!(f, "[{}, {}]!", rn, signed_offset),
+            (false, true) => write!(f, "[{}], {}", rn, signed_offset),
+            // Unindexed, with the offset passed to the coprocessor as an
+            // option
+            (false, false) => write!(f, "[{}], {{{}}}", rn, offset_8),
+        }
+    }
+}
+
 impl fmt::Display for AddressMode3 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let AddressMode3 {
@@ -1041,6 +1808,12 @@ impl fmt::Display for AddressMode3 {
     }
 }
 
+impl fmt::Display for AddressMode4 {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.rn, format_bool(self.w, "!"))
+    }
+}
+
 impl fmt::Display for Condition {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
@@ -1084,3 +1857,38 @@ fn format_bool(value: bool, string: &str) -> &str {
         ""
     }
 }
+
+fn format_block_mode(address: &AddressMode4) -> &str {
+    match (address.p, address.u) {
+        (false, true) => "ia",
+        (true, true) => "ib",
+        (false, false) => "da",
+        (true, false) => "db",
+    }
+}
+
+// Formats a register list, collapsing consecutive registers into ranges,
+// e.g. `{r0, r4-r7, lr}`.
+fn format_register_list(register_list: u32) -> String {
+    let mut ranges: Vec<String> = vec![];
+    let mut i = 0;
+    while i < 16 {
+        if !register_list.bit(i) {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < 16 && register_list.bit(i) {
+            i += 1;
+        }
+        let end = i - 1;
+
+        ranges.push(if start == end {
+            format!("{}", Register(start as u32))
+        } else {
+            format!("{}-{}", Register(start as u32), Register(end as u32))
+        });
+    }
+    format!("{{{}}}", ranges.join(", "))
+}
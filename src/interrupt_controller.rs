@@ -1,9 +1,8 @@
-use bus;
-use bit::{Bit, Bits, SetBit};
+use bus::{self, Read};
+use bit::{Bit, SetBit, SetBits};
 
 pub struct InterruptController {
     enabled: bool,
-    asserted: bool,
     mask: u16,
     flags: u16,
 }
@@ -30,38 +29,45 @@ impl InterruptController {
     pub fn new() -> InterruptController {
         InterruptController {
             enabled: false,
-            asserted: false,
             mask: 0,
             flags: 0,
         }
     }
 
+    /// Whether the IRQ line to the CPU is held, i.e. interrupts are enabled
+    /// and an enabled input has been asserted but not yet acknowledged.
     pub fn is_asserted(&self) -> bool {
-        self.asserted
+        self.enabled && self.mask & self.flags != 0
     }
 
-    pub fn assert(&mut self, input: Input) {
-        if self.mask.bit(input.clone() as u8) {
-            self.asserted = true;
-            self.flags.set_bit(input as u8, true);
-        }
+    /// Whether an enabled input has been asserted but not yet acknowledged,
+    /// regardless of IME. This is the condition that wakes a halted CPU.
+    pub fn is_pending(&self) -> bool {
+        self.mask & self.flags != 0
     }
 
-    pub fn reset(&mut self) {
-        self.asserted = false;
+    /// Whether an enabled input which wakes the CPU from STOP has been
+    /// asserted but not yet acknowledged. Only the keypad, game pak and
+    /// serial port keep running in STOP.
+    pub fn is_pending_wake_from_stop(&self) -> bool {
+        let inputs = 1 << Input::Key as u16
+            | 1 << Input::GamePak as u16
+            | 1 << Input::Serial as u16;
+        self.mask & self.flags & inputs != 0
+    }
+
+    /// Raise an interrupt request. The request is latched in IF until it is
+    /// acknowledged, even if the input is not enabled in IE.
+    pub fn assert(&mut self, input: Input) {
+        self.flags.set_bit(input as u8, true);
     }
 }
 
+// Byte and word accesses are made as accesses to the halfwords they cover
 impl bus::Read for InterruptController {
     fn read_byte(&self, address: u32) -> u8 {
-        match address {
-            0x200 => self.mask as u8,
-            0x201 => self.mask.bits(8..16) as u8,
-            0x202 => self.flags as u8,
-            0x203 => self.flags.bits(8..16) as u8,
-            0x208 => self.enabled as u8,
-            _ => unreachable!(),
-        }
+        let halfword = self.read_halfword(address & !1);
+        (halfword >> (8 * (address & 1))) as u8
     }
 
     fn read_halfword(&self, address: u32) -> u16 {
@@ -69,32 +75,45 @@ impl bus::Read for InterruptController {
             0x200 => self.mask,
             0x202 => self.flags,
             0x208 => self.enabled as u16,
+            // The unused upper half of IME's word
+            0x20A => 0,
             _ => unreachable!(),
         }
     }
 
-    fn read_word(&self, _address: u32) -> u32 {
-        unimplemented!();
+    fn read_word(&self, address: u32) -> u32 {
+        self.read_halfword(address) as u32
+            | (self.read_halfword(address + 2) as u32) << 16
     }
 }
 
 impl bus::Write for InterruptController {
-    fn write_byte(&mut self, _address: u32, _value: u8) {
-        unimplemented!();
+    fn write_byte(&mut self, address: u32, value: u8) {
+        let shift = 8 * (address & 1) as u8;
+        match address & !1 {
+            // Writing a 1 acknowledges a request, so the other byte of IF
+            // is written as 0s rather than its current value
+            0x202 => self.write_halfword(0x202, (value as u16) << shift),
+            address => {
+                let mut halfword = self.read_halfword(address);
+                halfword.set_bits(shift..shift + 8, value as u16);
+                self.write_halfword(address, halfword);
+            }
+        }
     }
 
     fn write_halfword(&mut self, address: u32, value: u16) {
         match address {
             0x200 => self.mask = value & 0x3fff,
             0x202 => self.flags ^= self.flags & value & 0x3fff,
-            0x208 if value == 0 => self.enabled = false,
-            0x208 if value == 1 => self.enabled = true,
-            0x208 => panic!("Attempted to write non-boolean value to IME"),
+            0x208 => self.enabled = value.bit(0),
+            0x20A => {}
             _ => unreachable!(),
         }
     }
 
-    fn write_word(&mut self, _address: u32, _value: u32) {
-        unimplemented!();
+    fn write_word(&mut self, address: u32, value: u32) {
+        self.write_halfword(address, value as u16);
+        self.write_halfword(address + 2, (value >> 16) as u16);
     }
 }
@@ -0,0 +1,119 @@
+use bit::{Bit, SetBit, SetBits};
+use bus::{Read, Write};
+use interrupt_controller::{Input, InterruptController};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// The ten buttons, numbered by their bit in KEYINPUT and KEYCNT.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Key {
+    A = 0,
+    B = 1,
+    Select = 2,
+    Start = 3,
+    Right = 4,
+    Left = 5,
+    Up = 6,
+    Down = 7,
+    R = 8,
+    L = 9,
+}
+
+/// The state of the buttons, which the frontend presses and releases, and
+/// the key interrupt control.
+pub struct Keypad {
+    // Buttons which are held, one bit per key
+    pressed: u16,
+    keycnt: u16,
+    interrupts: Rc<RefCell<InterruptController>>,
+}
+
+impl Keypad {
+    pub fn new(interrupts: Rc<RefCell<InterruptController>>) -> Keypad {
+        Keypad {
+            pressed: 0,
+            keycnt: 0,
+            interrupts: interrupts,
+        }
+    }
+
+    pub fn press(&mut self, key: Key) {
+        self.pressed.set_bit(key as u8, true);
+        self.update_interrupt();
+    }
+
+    pub fn release(&mut self, key: Key) {
+        self.pressed.set_bit(key as u8, false);
+        self.update_interrupt();
+    }
+
+    // Request a key interrupt if enabled and the selected keys are held.
+    // With bit 15 of KEYCNT set every selected key must be held, otherwise
+    // any one of them.
+    fn update_interrupt(&mut self) {
+        if !self.keycnt.bit(14) {
+            return;
+        }
+        let selected = self.keycnt & 0x3FF;
+        let held = self.pressed & selected;
+        let condition = if self.keycnt.bit(15) {
+            selected != 0 && held == selected
+        } else {
+            held != 0
+        };
+        if condition {
+            self.interrupts.borrow_mut().assert(Input::Key);
+        }
+    }
+}
+
+// KEYINPUT is read-only, with a bit clear for each key held
+impl Read for Keypad {
+    fn read_byte(&self, address: u32) -> u8 {
+        let halfword = self.read_halfword(address & !1);
+        if address.bit(0) {
+            (halfword >> 8) as u8
+        } else {
+            halfword as u8
+        }
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        match address {
+            0x130 => !self.pressed & 0x3FF,
+            0x132 => self.keycnt,
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        self.read_halfword(address) as u32
+            | (self.read_halfword(address + 2) as u32) << 16
+    }
+}
+
+impl Write for Keypad {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        match address {
+            0x130 | 0x131 => {}
+            0x132 => self.keycnt.set_bits(0..8, value as u16),
+            0x133 => self.keycnt.set_bits(8..16, value as u16 & 0xC3),
+            _ => unreachable!(),
+        }
+        self.update_interrupt();
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        match address {
+            0x130 => {}
+            0x132 => self.keycnt = value & 0xC3FF,
+            _ => unreachable!(),
+        }
+        self.update_interrupt();
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        self.write_halfword(address, value as u16);
+        self.write_halfword(address + 2, (value >> 16) as u16);
+    }
+}
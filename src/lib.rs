@@ -0,0 +1,45 @@
+//! A Game Boy Advance emulator.
+//!
+//! `Gba` is the whole system. Frontends drive it a frame or an instruction
+//! at a time, and receive its output through `VideoSink` and `AudioSink` or
+//! by reading it after each step.
+
+// The code is written in the 2015 edition's style
+#![allow(ellipsis_inclusive_range_patterns, bare_trait_objects)]
+#![allow(clippy::redundant_field_names)]
+
+extern crate byteorder;
+extern crate core;
+
+mod apu;
+mod audio;
+mod bios;
+mod bit;
+mod bus;
+mod cpu;
+mod cycles;
+mod decode;
+mod dma;
+mod execute;
+mod instruction;
+mod interrupt_controller;
+mod keypad;
+mod memory_map;
+mod png;
+mod ppu;
+mod ram;
+mod rom;
+mod scheduler;
+mod system;
+mod timer;
+mod video;
+mod wait_control;
+mod wav;
+
+pub use apu::SAMPLE_RATE;
+pub use audio::{AudioSink, Resampler};
+pub use keypad::Key;
+pub use ppu::{HEIGHT, WIDTH};
+pub use system::Gba;
+pub use video::{FrameDumper, ImageFormat, VideoSink};
+pub use wav::WavWriter;
@@ -1,30 +1,15 @@
-#![feature(box_syntax)]
-
-extern crate byteorder;
-extern crate core;
-#[macro_use]
+extern crate gba;
 extern crate structopt;
 
-mod bit;
-mod bus;
-mod cpu;
-mod decode;
-mod execute;
-mod instruction;
-mod interrupt_controller;
-mod memory_map;
-
-use cpu::Cpu;
-use interrupt_controller::InterruptController;
-use memory_map::MemoryMap;
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::*;
+use gba::{FrameDumper, Gba, ImageFormat, WavWriter};
+use std::fs;
 use std::path::PathBuf;
 use std::process;
-use std::rc::Rc;
 use structopt::StructOpt;
 
+// WAV files are recorded at a standard rate rather than the APU's own
+const WAV_SAMPLE_RATE: u32 = 48000;
+
 #[derive(StructOpt, Debug)]
 #[structopt(name = "GBA Emulator")]
 struct Opt {
@@ -34,7 +19,32 @@ struct Opt {
 
     /// Path to BIOS
     #[structopt(short = "b", long = "bios", parse(from_os_str))]
-    bios: PathBuf,
+    bios: Option<PathBuf>,
+
+    /// Service BIOS calls natively. Without --bios, boots straight into the
+    /// ROM using a built-in BIOS.
+    #[structopt(long = "hle-bios")]
+    hle_bios: bool,
+
+    /// Record the sound to a WAV file
+    #[structopt(long = "wav", parse(from_os_str))]
+    wav: Option<PathBuf>,
+
+    /// Write each frame to an image file in a directory
+    #[structopt(long = "dump-frames", parse(from_os_str))]
+    dump_frames: Option<PathBuf>,
+
+    /// Only write the frame with this number, counted from 0
+    #[structopt(long = "only-frame")]
+    only_frame: Option<u64>,
+
+    /// Write frames as PPM rather than PNG
+    #[structopt(long = "ppm")]
+    ppm: bool,
+
+    /// Exit after running this many frames
+    #[structopt(long = "frames")]
+    frames: Option<u64>,
 }
 
 fn main() {
@@ -49,14 +59,61 @@ fn main() {
 }
 
 fn run(opt: Opt) -> std::result::Result<(), String> {
-    let interrupts = Rc::new(RefCell::new(InterruptController::new()));
-    let bios = BufReader::new(File::open(opt.bios)
-        .map_err(|err| format!("Error reading BIOS:\n  {}", err))?);
-    let rom = BufReader::new(File::open(opt.rom)
-        .map_err(|err| format!("Error reading ROM:\n  {}", err))?);
-    let memory = MemoryMap::new(bios, rom, Rc::clone(&interrupts));
-    let mut cpu = Cpu::new(memory, Rc::clone(&interrupts));
-    loop {
-        cpu.tick();
+    let bios = match opt.bios {
+        Some(path) => Some(
+            fs::read(path)
+                .map_err(|err| format!("Error reading BIOS:\n  {}", err))?,
+        ),
+        None if opt.hle_bios => None,
+        None => return Err("A BIOS is required without --hle-bios".into()),
+    };
+    let rom = fs::read(opt.rom)
+        .map_err(|err| format!("Error reading ROM:\n  {}", err))?;
+    // Without the real BIOS there is no boot sequence to run
+    let mut gba = match bios {
+        Some(bios) => {
+            let mut gba = Gba::new(bios, rom)?;
+            gba.set_hle_bios(opt.hle_bios);
+            gba
+        }
+        None => Gba::with_hle_bios(rom)?,
+    };
+
+    if let Some(path) = opt.wav {
+        let writer = WavWriter::create(&path, WAV_SAMPLE_RATE)
+            .map_err(|err| format!("Error creating WAV file:\n  {}", err))?;
+        gba.set_audio_sink(Box::new(writer));
+    }
+    if let Some(directory) = opt.dump_frames {
+        let format = if opt.ppm {
+            ImageFormat::Ppm
+        } else {
+            ImageFormat::Png
+        };
+        let dumper = FrameDumper::new(directory, format, opt.only_frame)
+            .map_err(|err| {
+                format!("Error creating frame directory:\n  {}", err)
+            })?;
+        gba.set_video_sink(Box::new(dumper));
+    }
+
+    // Run until the number of frames asked for, or until the sound or
+    // frames can't be written
+    let mut frame = 0;
+    let mut unsupported = 0;
+    while opt.frames != Some(frame) {
+        gba.run_frame();
+        frame += 1;
+        for function in &gba.unsupported_bios_functions()[unsupported..] {
+            eprintln!("Unsupported BIOS function: {:#x}", function);
+        }
+        unsupported = gba.unsupported_bios_functions().len();
+        if gba.audio_error().is_some() || gba.video_error().is_some() {
+            break;
+        }
     }
+    gba.finish_audio()
+        .map_err(|err| format!("Error writing WAV file:\n  {}", err))?;
+    gba.finish_video()
+        .map_err(|err| format!("Error writing frames:\n  {}", err))
 }
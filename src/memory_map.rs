@@ -1,127 +1,495 @@
-use bit::SetBits;
-use bus::{Read, Write};
+use apu::Apu;
+use bit::{Bit, SetBits};
+use bus::{Read, Width, Write};
+use dma::{Dma, Timing};
 use interrupt_controller::InterruptController;
-use std::cell::RefCell;
-use std::fs::File;
-use std::io::*;
+use keypad::Keypad;
+use ppu::{Ppu, HEIGHT};
+use ram::Ram;
+use rom::Rom;
+use scheduler::{Event, Scheduler};
+use std::cell::{Cell, RefCell};
 use std::rc::Rc;
+use timer::Timers;
+use wait_control::WaitControl;
+
+// The addresses of FIFO_A and FIFO_B, which DMA channels 1 and 2 write
+const FIFO_ADDRESSES: [u32; 2] = [0x40000A0, 0x40000A4];
 
 pub struct MemoryMap {
-    bios: RefCell<BufReader<File>>,
-    rom: RefCell<BufReader<File>>,
+    bios: Rom,
+    rom: Rom,
+    ewram: Ram,
+    iwram: Ram,
+    ppu: Ppu,
+    apu: Apu,
+    dma: Dma,
+    timers: Timers,
+    keypad: Keypad,
+    serial: Serial,
     interrupts: Rc<RefCell<InterruptController>>,
+    wait_control: WaitControl,
+    scheduler: Rc<RefCell<Scheduler>>,
     misc: MiscRegisters,
+    // Cycles taken by accesses beyond the single cycle each is counted as by
+    // the CPU, since they were last taken
+    wait_cycles: Cell<u32>,
+    // The address following the last access, which the next access must be
+    // at to be sequential
+    next_address: Cell<u32>,
+    // The last unit transferred by DMA, which remains on the bus
+    dma_open_bus: u32,
 }
 
 impl MemoryMap {
     pub fn new(
-        bios: BufReader<File>,
-        rom: BufReader<File>,
+        bios: Rom,
+        rom: Rom,
         interrupts: Rc<RefCell<InterruptController>>,
     ) -> MemoryMap {
+        let scheduler = Rc::new(RefCell::new(Scheduler::new()));
         MemoryMap {
-            bios: RefCell::new(bios),
-            rom: RefCell::new(rom),
+            bios: bios,
+            rom: rom,
+            ewram: Ram::new(0x40000),
+            iwram: Ram::new(0x8000),
+            ppu: Ppu::new(Rc::clone(&interrupts), Rc::clone(&scheduler)),
+            apu: Apu::new(Rc::clone(&scheduler)),
+            dma: Dma::new(Rc::clone(&interrupts)),
+            timers: Timers::new(Rc::clone(&interrupts), Rc::clone(&scheduler)),
+            keypad: Keypad::new(Rc::clone(&interrupts)),
+            serial: Serial,
             interrupts: interrupts,
+            wait_control: WaitControl::new(),
+            scheduler: scheduler,
             misc: MiscRegisters::new(),
+            wait_cycles: Cell::new(0),
+            next_address: Cell::new(0),
+            dma_open_bus: 0,
+        }
+    }
+
+    /// Fetch an ARM instruction, using the game pak prefetch buffer.
+    pub fn fetch_word(&self, address: u32) -> u32 {
+        self.charge(address, Width::Word, true);
+        self.try_map_read(address)
+            .map_or(0, |(device, offset)| device.read_word(offset))
+    }
+
+    /// Fetch a Thumb instruction, using the game pak prefetch buffer.
+    pub fn fetch_halfword(&self, address: u32) -> u16 {
+        self.charge(address, Width::Halfword, true);
+        self.try_map_read(address)
+            .map_or(0, |(device, offset)| device.read_halfword(offset))
+    }
+
+    /// Take the wait states of the accesses made since they were last taken.
+    /// The CPU counts each access as a single cycle, so only the cycles
+    /// beyond that are included.
+    pub fn take_wait_cycles(&self) -> u32 {
+        self.wait_cycles.replace(0)
+    }
+
+    /// Let the bus sit idle for a number of cycles while the CPU works
+    /// internally, which the prefetch buffer can use.
+    pub fn idle(&self, cycles: u32) {
+        self.wait_control.idle(cycles);
+    }
+
+    // Record the time taken by an access. Only instruction fetches can be
+    // served by the prefetch buffer, and other game pak accesses stop it.
+    fn charge(&self, address: u32, width: Width, fetch: bool) {
+        let sequential = address == self.next_address.get();
+        self.charge_access(address, width, sequential, fetch);
+    }
+
+    fn charge_access(
+        &self,
+        address: u32,
+        width: Width,
+        sequential: bool,
+        fetch: bool,
+    ) {
+        self.next_address.set(address.wrapping_add(width.bytes()));
+
+        let cycles = match address >> 24 {
+            0x8...0xD if fetch => {
+                self.wait_control.fetch_cycles(address, width, sequential)
+            }
+            0x8...0xF => {
+                self.wait_control.stop_prefetch();
+                self.wait_control.access_cycles(address, width, sequential)
+            }
+            _ => {
+                let cycles =
+                    self.wait_control.access_cycles(address, width, sequential);
+                self.wait_control.idle(cycles);
+                cycles
+            }
+        };
+        self.wait_cycles.set(self.wait_cycles.get() + cycles - 1);
+    }
+
+    /// Advance the devices on the bus by a number of cycles, handling the
+    /// events which fall due. DMA transfers started by the CPU or by events
+    /// stall the CPU, so take further time.
+    pub fn tick(&mut self, cycles: u32) {
+        let mut cycles = cycles;
+        while cycles > 0 {
+            self.scheduler.borrow_mut().advance(cycles as u64);
+            self.handle_events();
+            cycles = self.run_dma();
         }
     }
 
-    // fn map_read<'a, T>(&'a self, address: u32, read: fn(&'a Read, u32) -> T) -> T {
-    fn map_read(&self, address: u32) -> (&Read, u32) {
+    fn handle_events(&mut self) {
+        loop {
+            let (event, time) = match self.scheduler.borrow_mut().pop_due() {
+                Some(due) => due,
+                None => break,
+            };
+            match event {
+                Event::HBlank => {
+                    self.ppu.handle_event(event, time);
+                    if (self.ppu.vcount() as usize) < HEIGHT {
+                        self.dma.trigger(Timing::HBlank);
+                    }
+                }
+                Event::EndOfLine => {
+                    self.ppu.handle_event(event, time);
+                    if self.ppu.vcount() as usize == HEIGHT {
+                        self.dma.trigger(Timing::VBlank);
+                    }
+                    // Video capture transfers a line at a time from line 2
+                    // to line 161
+                    match self.ppu.vcount() {
+                        2...161 => self.dma.request_video_capture(),
+                        162 => self.dma.stop_video_capture(),
+                        _ => {}
+                    }
+                }
+                Event::TimerOverflow(index) => {
+                    let overflowed = self.timers.overflow(index, time);
+                    self.timers_overflowed(overflowed);
+                }
+                Event::FrameSequencer | Event::Sample => {
+                    self.apu.handle_event(event, time);
+                }
+            }
+        }
+    }
+
+    // Make the transfers of the DMA channels which have started, returning
+    // the number of cycles the CPU is stalled for. Each unit is read then
+    // written, with the first read and write non-sequential, and a transfer
+    // takes two internal cycles. Transfers can't fault, so reads of unmapped
+    // memory give the last unit transferred again and writes to unmapped or
+    // read-only memory are dropped.
+    fn run_dma(&mut self) -> u32 {
+        let mut cycles = 0;
+        while let Some(transfer) = self.dma.next_transfer() {
+            let width = transfer.width;
+            let mut source = transfer.source;
+            let mut destination = transfer.destination;
+            for unit in 0..transfer.count {
+                let sequential = unit != 0;
+                self.charge_access(source, width, sequential, false);
+                let value = match self.try_map_read(source) {
+                    Some((device, offset)) => match width {
+                        Width::Word => device.read_word(offset),
+                        _ => device.read_halfword(offset) as u32,
+                    },
+                    None => self.dma_open_bus,
+                };
+                self.dma_open_bus = value;
+                self.charge_access(destination, width, sequential, false);
+                if let Some((device, offset)) = self.try_map_write(destination)
+                {
+                    match width {
+                        Width::Word => device.write_word(offset, value),
+                        _ => device.write_halfword(offset, value as u16),
+                    }
+                }
+                source = source.wrapping_add(transfer.source_step);
+                destination =
+                    destination.wrapping_add(transfer.destination_step);
+            }
+            self.dma.finish(&transfer);
+            cycles += 2 * transfer.count + 2 + self.take_wait_cycles();
+        }
+        cycles
+    }
+
+    /// The number of cycles until the next device event, before which
+    /// nothing can change unless the CPU acts.
+    pub fn cycles_until_next_event(&self) -> u32 {
+        match self.scheduler.borrow().cycles_until_next() {
+            Some(cycles) => cycles.max(1).min(u32::MAX as u64) as u32,
+            None => 1,
+        }
+    }
+
+    // Timers 0 and 1 clock the sound FIFOs, each of which takes its next
+    // sample when its timer overflows and has DMA refill it once it is half
+    // empty
+    fn timers_overflowed(&mut self, overflowed: [bool; 4]) {
+        for (fifo, &address) in FIFO_ADDRESSES.iter().enumerate() {
+            let timer = self.apu.fifo_timer(fifo);
+            if overflowed[timer] && self.apu.pop_fifo(fifo) {
+                self.dma.request_sound(address);
+            }
+        }
+    }
+
+    pub fn ppu(&self) -> &Ppu {
+        &self.ppu
+    }
+
+    pub fn ppu_mut(&mut self) -> &mut Ppu {
+        &mut self.ppu
+    }
+
+    pub fn apu(&self) -> &Apu {
+        &self.apu
+    }
+
+    pub fn apu_mut(&mut self) -> &mut Apu {
+        &mut self.apu
+    }
+
+    /// The buttons, for the frontend to press and release.
+    pub fn keypad_mut(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    /// The power mode last requested by a write to HALTCNT.
+    pub fn power_mode(&self) -> PowerMode {
+        self.misc.power_mode
+    }
+
+    pub fn set_power_mode(&mut self, mode: PowerMode) {
+        self.misc.power_mode = mode;
+    }
+
+    // Accesses for debugging, which take no time and leave the bus as it
+    // was. Addresses are aligned to the size of the access, and unmapped
+    // addresses read as None and ignore writes, returning false.
+
+    pub fn debug_read_byte(&self, address: u32) -> Option<u8> {
+        self.try_map_read(address)
+            .map(|(device, offset)| device.read_byte(offset))
+    }
+
+    pub fn debug_read_halfword(&self, address: u32) -> Option<u16> {
+        self.try_map_read(address & !1)
+            .map(|(device, offset)| device.read_halfword(offset))
+    }
+
+    pub fn debug_read_word(&self, address: u32) -> Option<u32> {
+        self.try_map_read(address & !3)
+            .map(|(device, offset)| device.read_word(offset))
+    }
+
+    pub fn debug_write_byte(&mut self, address: u32, value: u8) -> bool {
+        match self.try_map_write(address) {
+            Some((device, offset)) => {
+                device.write_byte(offset, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn debug_write_halfword(&mut self, address: u32, value: u16) -> bool {
+        match self.try_map_write(address & !1) {
+            Some((device, offset)) => {
+                device.write_halfword(offset, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn debug_write_word(&mut self, address: u32, value: u32) -> bool {
+        match self.try_map_write(address & !3) {
+            Some((device, offset)) => {
+                device.write_word(offset, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    // Find the device an address can be read from, if any. Unmapped memory,
+    // including the game pak SRAM which isn't emulated, reads as 0 and
+    // ignores writes.
+    fn try_map_read(&self, address: u32) -> Option<(&Read, u32)> {
         let offset = address & 0xFFFFFF;
-        match address {
+        let mapped: (&Read, u32) = match address {
             // General Internal Memory
             0x0000000...0x0003FFF => (&self.bios, offset),
-            0x2000000...0x203FFFF => panic!("WRAM - On-board Work RAM"),
-            0x3000000...0x3007FFF => panic!("WRAM - On-chip Work RAM"),
+            // Work RAM is mirrored across its 16 MiB window, so the BIOS's
+            // accesses to 0x3FFFF00-0x3FFFFFF reach the end of on-chip WRAM
+            0x2000000...0x2FFFFFF => (&self.ewram, offset),
+            0x3000000...0x3FFFFFF => (&self.iwram, offset),
             // I/O Map
-            0x4000000...0x4000056 => panic!("LCD I/O Registers"),
-            0x4000060...0x40000A8 => panic!("Sound Registers"),
-            0x40000B0...0x40000E0 => panic!("DMA Transfer Channels"),
-            0x4000100...0x4000110 => panic!("Timer Registers"),
-            0x4000120...0x400012C => panic!("Serial Communication (1)"),
-            0x4000130...0x4000132 => panic!("Keypad Input"),
-            0x4000134...0x400015A => panic!("Serial Communication (2)"),
-            0x4000200...0x4000209 => (&self.interrupts, offset),
-            0x4000300...0x4000803 => (&self.misc, offset),
+            0x4000000...0x4000056 => (&self.ppu, address),
+            0x4000060...0x40000AF => (&self.apu, offset),
+            0x40000B0...0x40000DF => (&self.dma, offset),
+            0x4000100...0x400010F => (&self.timers, offset),
+            0x4000120...0x400012F => (&self.serial, offset),
+            0x4000130...0x4000133 => (&self.keypad, offset),
+            0x4000134...0x400015B => (&self.serial, offset),
+            0x4000200...0x4000203 => (&self.interrupts, offset),
+            0x4000204...0x4000207 => (&self.wait_control, offset),
+            0x4000208...0x4000209 => (&self.interrupts, offset),
+            0x4000300...0x4000301 => (&self.misc, offset),
+            0x4000800...0x4000803 => (&self.misc, offset),
             // Internal Display Memory
-            0x5000000...0x50003FF => panic!("BG/OBJ Palette RAM"),
-            0x6000000...0x6017FFF => panic!("VRAM - Video RAM"),
-            0x7000000...0x70003FF => panic!("OAM - OBJ Attributes"),
+            // The PPU decodes the whole address as it handles several regions
+            // and their mirrors
+            0x5000000...0x5FFFFFF => (&self.ppu, address),
+            0x6000000...0x6FFFFFF => (&self.ppu, address),
+            0x7000000...0x7FFFFFF => (&self.ppu, address),
             // External Memory (Game Pak)
-            0x7ffffff...0x9FFFFFF => (&self.rom, offset),
-            0xA000000...0xBFFFFFF => (&self.rom, offset),
-            0xC000000...0xDFFFFFF => (&self.rom, offset),
-            0xE000000...0xE00FFFF => panic!("Game Pak SRAM"),
-            _ => panic!("Memory address {:#x} is unreadable", address),
-        }
+            // ROM is mirrored in each of the three wait state regions
+            0x8000000...0x9FFFFFF => (&self.rom, address & 0x1FFFFFF),
+            0xA000000...0xBFFFFFF => (&self.rom, address & 0x1FFFFFF),
+            0xC000000...0xDFFFFFF => (&self.rom, address & 0x1FFFFFF),
+            _ => return None,
+        };
+        Some(mapped)
     }
 
-    fn map_write(&mut self, address: u32) -> (&mut Write, u32) {
+    // Find the device an address can be written to, if any
+    fn try_map_write(&mut self, address: u32) -> Option<(&mut Write, u32)> {
         let offset = address & 0xFFFFFF;
-        match address {
+        let mapped: (&mut Write, u32) = match address {
             // General Internal Memory
-            0x2000000...0x203FFFF => panic!("WRAM - On-board Work RAM"),
-            0x3000000...0x3007FFF => panic!("WRAM - On-chip Work RAM"),
+            0x2000000...0x2FFFFFF => (&mut self.ewram, offset),
+            0x3000000...0x3FFFFFF => (&mut self.iwram, offset),
             // I/O Map
-            0x4000000...0x4000056 => panic!("LCD I/O Registers"),
-            0x4000060...0x40000A8 => panic!("Sound Registers"),
-            0x40000B0...0x40000E0 => panic!("DMA Transfer Channels"),
-            0x4000100...0x4000110 => panic!("Timer Registers"),
-            0x4000120...0x400012C => panic!("Serial Communication (1)"),
-            0x4000130...0x4000132 => panic!("Keypad Input"),
-            0x4000134...0x400015A => panic!("Serial Communication (2)"),
-            0x4000200...0x4000209 => (&mut self.interrupts, offset),
-            0x4000300...0x4000803 => (&mut self.misc, offset),
+            0x4000000...0x4000056 => (&mut self.ppu, address),
+            0x4000060...0x40000AF => (&mut self.apu, offset),
+            0x40000B0...0x40000DF => (&mut self.dma, offset),
+            0x4000100...0x400010F => (&mut self.timers, offset),
+            0x4000120...0x400012F => (&mut self.serial, offset),
+            0x4000130...0x4000133 => (&mut self.keypad, offset),
+            0x4000134...0x400015B => (&mut self.serial, offset),
+            0x4000200...0x4000203 => (&mut self.interrupts, offset),
+            0x4000204...0x4000207 => (&mut self.wait_control, offset),
+            0x4000208...0x4000209 => (&mut self.interrupts, offset),
+            0x4000300...0x4000301 => (&mut self.misc, offset),
+            0x4000800...0x4000803 => (&mut self.misc, offset),
             // Internal Display Memory
-            0x5000000...0x50003FF => panic!("BG/OBJ Palette RAM"),
-            0x6000000...0x6017FFF => panic!("VRAM - Video RAM"),
-            0x7000000...0x70003FF => panic!("OAM - OBJ Attributes"),
-            // External Memory (Game Pak)
-            0xE000000...0xE00FFFF => panic!("Game Pak SRAM"),
-            _ => panic!("Memory address {:#x} is unwritable", address),
-        }
+            0x5000000...0x5FFFFFF => (&mut self.ppu, address),
+            0x6000000...0x6FFFFFF => (&mut self.ppu, address),
+            0x7000000...0x7FFFFFF => (&mut self.ppu, address),
+            _ => return None,
+        };
+        Some(mapped)
     }
 }
 
+// Halfword and word accesses ignore the low bits of the address, so that
+// devices only see addresses aligned to the access
 impl Read for MemoryMap {
     fn read_byte(&self, address: u32) -> u8 {
-        let (device, offset) = self.map_read(address);
-        device.read_byte(offset)
+        self.charge(address, Width::Byte, false);
+        self.try_map_read(address)
+            .map_or(0, |(device, offset)| device.read_byte(offset))
     }
 
     fn read_halfword(&self, address: u32) -> u16 {
-        let (device, offset) = self.map_read(address);
-        device.read_halfword(offset)
+        let address = address & !1;
+        self.charge(address, Width::Halfword, false);
+        self.try_map_read(address)
+            .map_or(0, |(device, offset)| device.read_halfword(offset))
     }
 
     fn read_word(&self, address: u32) -> u32 {
-        let (device, offset) = self.map_read(address);
-        device.read_word(offset)
+        let address = address & !3;
+        self.charge(address, Width::Word, false);
+        self.try_map_read(address)
+            .map_or(0, |(device, offset)| device.read_word(offset))
     }
 }
 
 impl Write for MemoryMap {
     fn write_byte(&mut self, address: u32, value: u8) {
-        let (device, offset) = self.map_write(address);
-        device.write_byte(offset, value);
+        self.charge(address, Width::Byte, false);
+        if let Some((device, offset)) = self.try_map_write(address) {
+            device.write_byte(offset, value);
+        }
     }
 
     fn write_halfword(&mut self, address: u32, value: u16) {
-        let (device, offset) = self.map_write(address);
-        device.write_halfword(offset, value);
+        let address = address & !1;
+        self.charge(address, Width::Halfword, false);
+        if let Some((device, offset)) = self.try_map_write(address) {
+            device.write_halfword(offset, value);
+        }
     }
 
     fn write_word(&mut self, address: u32, value: u32) {
-        let (device, offset) = self.map_write(address);
-        device.write_word(offset, value);
+        let address = address & !3;
+        self.charge(address, Width::Word, false);
+        if let Some((device, offset)) = self.try_map_write(address) {
+            device.write_word(offset, value);
+        }
     }
 }
 
+/// The low power modes entered by writing to HALTCNT. The CPU stops executing
+/// instructions until it is woken by an interrupt.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PowerMode {
+    Running,
+    // Woken by any interrupt enabled in IE
+    Halted,
+    // Woken only by keypad, game pak and serial interrupts
+    Stopped,
+}
+
+impl PowerMode {
+    fn from_haltcnt(value: u8) -> PowerMode {
+        if value.bit(7) {
+            PowerMode::Stopped
+        } else {
+            PowerMode::Halted
+        }
+    }
+}
+
+// Nothing is ever connected to the serial port, so its registers read as 0
+// and ignore writes
+struct Serial;
+
+impl Read for Serial {
+    fn read_byte(&self, _address: u32) -> u8 {
+        0
+    }
+
+    fn read_halfword(&self, _address: u32) -> u16 {
+        0
+    }
+
+    fn read_word(&self, _address: u32) -> u32 {
+        0
+    }
+}
+
+impl Write for Serial {
+    fn write_byte(&mut self, _address: u32, _value: u8) {}
+
+    fn write_halfword(&mut self, _address: u32, _value: u16) {}
+
+    fn write_word(&mut self, _address: u32, _value: u32) {}
+}
+
 struct MiscRegisters {
     postflg: bool,
-    haltcnt: bool,
+    power_mode: PowerMode,
     memcnt: u32,
 }
 
@@ -129,7 +497,7 @@ impl MiscRegisters {
     fn new() -> MiscRegisters {
         MiscRegisters {
             postflg: false,
-            haltcnt: false,
+            power_mode: PowerMode::Running,
             memcnt: 0,
         }
     }
@@ -139,7 +507,8 @@ impl Read for MiscRegisters {
     fn read_byte(&self, address: u32) -> u8 {
         match address {
             0x300 => self.postflg as u8,
-            0x301 => self.haltcnt as u8,
+            // HALTCNT is write-only
+            0x301 => 0,
             0x800 => self.memcnt as u8,
             0x801 => (self.memcnt >> 8) as u8,
             0x802 => (self.memcnt >> 16) as u8,
@@ -151,7 +520,8 @@ impl Read for MiscRegisters {
     fn read_halfword(&self, address: u32) -> u16 {
         match address {
             0x300 => self.postflg as u16,
-            0x301 => self.haltcnt as u16,
+            // HALTCNT is write-only
+            0x301 => 0,
             0x800 => self.memcnt as u16,
             0x802 => (self.memcnt >> 16) as u16,
             _ => unreachable!(),
@@ -161,7 +531,8 @@ impl Read for MiscRegisters {
     fn read_word(&self, address: u32) -> u32 {
         match address {
             0x300 => self.postflg as u32,
-            0x301 => self.haltcnt as u32,
+            // HALTCNT is write-only
+            0x301 => 0,
             0x800 => self.memcnt,
             _ => unreachable!(),
         }
@@ -171,12 +542,8 @@ impl Read for MiscRegisters {
 impl Write for MiscRegisters {
     fn write_byte(&mut self, address: u32, value: u8) {
         match address {
-            0x300 if value == 0 => self.postflg = false,
-            0x300 if value == 1 => self.postflg = true,
-            0x300 => panic!("Attempted to write non-boolean value to POSTFLG"),
-            0x301 if value == 0 => self.haltcnt = false,
-            0x301 if value == 1 => self.haltcnt = true,
-            0x301 => panic!("Attempted to write non-boolean value to HALTCNT"),
+            0x300 => self.postflg = value.bit(0),
+            0x301 => self.power_mode = PowerMode::from_haltcnt(value),
             0x800 => self.memcnt.set_bits(0..8, value as u32),
             0x801 => self.memcnt.set_bits(8..16, value as u32),
             0x802 => self.memcnt.set_bits(16..24, value as u32),
@@ -187,12 +554,8 @@ impl Write for MiscRegisters {
 
     fn write_halfword(&mut self, address: u32, value: u16) {
         match address {
-            0x300 if value == 0 => self.postflg = false,
-            0x300 if value == 1 => self.postflg = true,
-            0x300 => panic!("Attempted to write non-boolean value to POSTFLG"),
-            0x301 if value == 0 => self.haltcnt = false,
-            0x301 if value == 1 => self.haltcnt = true,
-            0x301 => panic!("Attempted to write non-boolean value to HALTCNT"),
+            0x300 => self.postflg = value.bit(0),
+            0x301 => self.power_mode = PowerMode::from_haltcnt(value as u8),
             0x800 => self.memcnt.set_bits(0..16, value as u32),
             0x802 => self.memcnt.set_bits(16..32, value as u32),
             _ => unreachable!(),
@@ -201,12 +564,8 @@ impl Write for MiscRegisters {
 
     fn write_word(&mut self, address: u32, value: u32) {
         match address {
-            0x300 if value == 0 => self.postflg = false,
-            0x300 if value == 1 => self.postflg = true,
-            0x300 => panic!("Attempted to write non-boolean value to POSTFLG"),
-            0x301 if value == 0 => self.haltcnt = false,
-            0x301 if value == 1 => self.haltcnt = true,
-            0x301 => panic!("Attempted to write non-boolean value to HALTCNT"),
+            0x300 => self.postflg = value.bit(0),
+            0x301 => self.power_mode = PowerMode::from_haltcnt(value as u8),
             0x800 => self.memcnt = value,
             _ => unreachable!(),
         }
@@ -0,0 +1,180 @@
+use byteorder::{BigEndian, WriteBytesExt};
+use std::io::{self, Write};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+// The most data a stored deflate block can hold
+const MAX_BLOCK_SIZE: usize = 0xFFFF;
+
+/// Write an image of 0xRRGGBB pixels as an 8 bit RGB PNG.
+///
+/// The image data is stored without compression, which keeps the encoder
+/// small and is fast enough for dumping frames.
+pub fn write<W: Write>(
+    writer: &mut W,
+    width: usize,
+    height: usize,
+    pixels: &[u32],
+) -> io::Result<()> {
+    writer.write_all(SIGNATURE)?;
+
+    let mut header = Vec::new();
+    header.write_u32::<BigEndian>(width as u32)?;
+    header.write_u32::<BigEndian>(height as u32)?;
+    // 8 bits per channel of RGB, with the standard compression and filter
+    // methods and no interlacing
+    header.write_all(&[8, 2, 0, 0, 0])?;
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // Each row is preceded by its filter type, which is none
+    let mut data = Vec::with_capacity(height * (1 + 3 * width));
+    for row in pixels.chunks(width).take(height) {
+        data.push(0);
+        for pixel in row {
+            data.push((pixel >> 16) as u8);
+            data.push((pixel >> 8) as u8);
+            data.push(*pixel as u8);
+        }
+    }
+    write_chunk(writer, b"IDAT", &zlib_stored(&data))?;
+
+    write_chunk(writer, b"IEND", &[])
+}
+
+fn write_chunk<W: Write>(
+    writer: &mut W,
+    kind: &[u8],
+    data: &[u8],
+) -> io::Result<()> {
+    writer.write_u32::<BigEndian>(data.len() as u32)?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    let crc = crc32(kind.iter().chain(data.iter()));
+    writer.write_u32::<BigEndian>(crc)
+}
+
+// Wrap data in a zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks = data.chunks(MAX_BLOCK_SIZE).len();
+    for (index, block) in data.chunks(MAX_BLOCK_SIZE).enumerate() {
+        // The header marks the final block, and is followed by the length
+        // and its complement
+        let last = index + 1 == blocks;
+        let length = block.len() as u16;
+        stream.push(last as u8);
+        stream.push(length as u8);
+        stream.push((length >> 8) as u8);
+        stream.push(!length as u8);
+        stream.push((!length >> 8) as u8);
+        stream.extend_from_slice(block);
+    }
+    let checksum = adler32(data);
+    stream.push((checksum >> 24) as u8);
+    stream.push((checksum >> 16) as u8);
+    stream.push((checksum >> 8) as u8);
+    stream.push(checksum as u8);
+    stream
+}
+
+fn crc32<'a, I: Iterator<Item = &'a u8>>(bytes: I) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB88320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::ByteOrder;
+
+    #[test]
+    fn crc32_matches_the_standard_check_value() {
+        assert_eq!(crc32(b"123456789".iter()), 0xCBF43926);
+        assert_eq!(crc32(b"IEND".iter()), 0xAE426082);
+    }
+
+    #[test]
+    fn adler32_matches_known_values() {
+        assert_eq!(adler32(b""), 1);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E60398);
+        // Both sums wrap at the modulus
+        assert_eq!(adler32(&[0xFF; 5552]), 0xF18F9B8C);
+    }
+
+    #[test]
+    fn large_data_is_split_into_stored_blocks() {
+        let data: Vec<u8> =
+            (0..MAX_BLOCK_SIZE + 10).map(|i| i as u8).collect();
+        let stream = zlib_stored(&data);
+        assert_eq!(&stream[0..2], &[0x78, 0x01]);
+        // The zlib header is a multiple of 31
+        assert_eq!((stream[0] as u32 * 256 + stream[1] as u32) % 31, 0);
+
+        let first = &stream[2..];
+        assert_eq!(&first[0..5], &[0, 0xFF, 0xFF, 0x00, 0x00]);
+        assert_eq!(&first[5..5 + MAX_BLOCK_SIZE], &data[..MAX_BLOCK_SIZE]);
+
+        let second = &first[5 + MAX_BLOCK_SIZE..];
+        assert_eq!(&second[0..5], &[1, 10, 0, !10, 0xFF]);
+        assert_eq!(&second[5..15], &data[MAX_BLOCK_SIZE..]);
+
+        let checksum = adler32(&data);
+        assert_eq!(
+            &second[15..],
+            &[
+                (checksum >> 24) as u8,
+                (checksum >> 16) as u8,
+                (checksum >> 8) as u8,
+                checksum as u8,
+            ]
+        );
+    }
+
+    #[test]
+    fn an_image_is_written_as_checksummed_chunks() {
+        let mut output = Vec::new();
+        write(&mut output, 2, 1, &[0x123456, 0xABCDEF]).unwrap();
+        assert_eq!(&output[0..8], SIGNATURE);
+
+        // Walk the chunks, checking each one's CRC
+        let mut kinds = Vec::new();
+        let mut rest = &output[8..];
+        while !rest.is_empty() {
+            let length = BigEndian::read_u32(&rest[0..4]) as usize;
+            let body = &rest[4..8 + length];
+            let crc = BigEndian::read_u32(&rest[8 + length..12 + length]);
+            assert_eq!(crc, crc32(body.iter()));
+            kinds.push(&body[0..4]);
+            if &body[0..4] == b"IHDR" {
+                let header = [0, 0, 0, 2, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+                assert_eq!(&body[4..], &header);
+            }
+            if &body[0..4] == b"IDAT" {
+                let pixels = [0, 0x12, 0x34, 0x56, 0xAB, 0xCD, 0xEF];
+                assert_eq!(&body[4..], &zlib_stored(&pixels)[..]);
+            }
+            rest = &rest[12 + length..];
+        }
+        let expected: Vec<&[u8]> = vec![b"IHDR", b"IDAT", b"IEND"];
+        assert_eq!(kinds, expected);
+    }
+}
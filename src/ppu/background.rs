@@ -0,0 +1,201 @@
+use bit::{Bit, Bits, SetBits};
+use bus::Read;
+use ppu::{Ppu, WIDTH};
+
+// Tile data for backgrounds can't be read from OBJ VRAM
+const BG_VRAM_SIZE: u32 = 0x10000;
+
+/// The rotation and scaling parameters of BG2 or BG3.
+///
+/// The reference point registers are copied to internal registers at the
+/// start of each frame or when written, and the internal registers are
+/// advanced by (PB, PD) after each line.
+pub struct AffineParameters {
+    pub pa: i16,
+    pub pb: i16,
+    pub pc: i16,
+    pub pd: i16,
+    x: u32,
+    y: u32,
+    internal_x: i32,
+    internal_y: i32,
+}
+
+impl AffineParameters {
+    pub fn new() -> AffineParameters {
+        AffineParameters {
+            pa: 0x100,
+            pb: 0,
+            pc: 0,
+            pd: 0x100,
+            x: 0,
+            y: 0,
+            internal_x: 0,
+            internal_y: 0,
+        }
+    }
+
+    /// Half of the X reference point as last written, where `high` selects
+    /// bits 16-27.
+    pub fn x(&self, high: bool) -> u16 {
+        (if high { self.x >> 16 } else { self.x }) as u16
+    }
+
+    /// Half of the Y reference point as last written, where `high` selects
+    /// bits 16-27.
+    pub fn y(&self, high: bool) -> u16 {
+        (if high { self.y >> 16 } else { self.y }) as u16
+    }
+
+    /// Write half of the X reference point, where `high` selects bits
+    /// 16-27.
+    pub fn set_x(&mut self, high: bool, value: u16) {
+        let range = if high { 16..28 } else { 0..16 };
+        self.x.set_bits(range, value as u32);
+        self.internal_x = reference_point(self.x);
+    }
+
+    /// Write half of the Y reference point, where `high` selects bits
+    /// 16-27.
+    pub fn set_y(&mut self, high: bool, value: u16) {
+        let range = if high { 16..28 } else { 0..16 };
+        self.y.set_bits(range, value as u32);
+        self.internal_y = reference_point(self.y);
+    }
+
+    pub fn reload(&mut self) {
+        self.internal_x = reference_point(self.x);
+        self.internal_y = reference_point(self.y);
+    }
+
+    pub fn advance(&mut self) {
+        self.internal_x = self.internal_x.wrapping_add(self.pb as i32);
+        self.internal_y = self.internal_y.wrapping_add(self.pd as i32);
+    }
+}
+
+// Sign extend a 28 bit 20.8 fixed point reference point
+fn reference_point(value: u32) -> i32 {
+    ((value << 4) as i32) >> 4
+}
+
+/// A text background, made of a scrolling map of 4bpp or 8bpp tiles.
+pub fn render_text(
+    ppu: &Ppu,
+    bg: usize,
+    line: usize,
+    pixels: &mut [Option<u16>],
+) {
+    let control = ppu.bgcnt[bg];
+    let char_base = control.bits(2..4) as u32 * 0x4000;
+    let screen_base = control.bits(8..13) as u32 * 0x800;
+    let colors_256 = control.bit(7);
+    let (width, height) = match control.bits(14..16) {
+        0 => (256, 256),
+        1 => (512, 256),
+        2 => (256, 512),
+        _ => (512, 512),
+    };
+
+    let y = (line as u32 + ppu.bgvofs[bg] as u32) & (height - 1);
+    for (x, pixel) in pixels.iter_mut().enumerate().take(WIDTH) {
+        let x_offset = (x as u32 + ppu.bghofs[bg] as u32) & (width - 1);
+
+        // Each screen block holds 32x32 tiles. Larger backgrounds use
+        // consecutive screen blocks, left to right then top to bottom.
+        let block = (y / 256) * (width / 256) + x_offset / 256;
+        let map_index = ((y % 256) / 8) * 32 + (x_offset % 256) / 8;
+        let entry = ppu.vram
+            .read_halfword(screen_base + block * 0x800 + map_index * 2);
+
+        let tile = entry.bits(0..10) as u32;
+        let mut tile_x = x_offset % 8;
+        let mut tile_y = y % 8;
+        if entry.bit(10) {
+            tile_x = 7 - tile_x;
+        }
+        if entry.bit(11) {
+            tile_y = 7 - tile_y;
+        }
+
+        *pixel = if colors_256 {
+            let address = char_base + tile * 64 + tile_y * 8 + tile_x;
+            tile_color_256(ppu, address)
+        } else {
+            let address = char_base + tile * 32 + tile_y * 4 + tile_x / 2;
+            let palette_bank = entry.bits(12..16) as u32;
+            tile_color_16(ppu, address, tile_x.bit(0), palette_bank)
+        };
+    }
+}
+
+/// A rotation/scaling background, made of a map of 8bpp tiles. It is drawn
+/// as it was `lines_back` lines above, for vertical mosaic.
+pub fn render_affine(
+    ppu: &Ppu,
+    bg: usize,
+    lines_back: u32,
+    pixels: &mut [Option<u16>],
+) {
+    let control = ppu.bgcnt[bg];
+    let char_base = control.bits(2..4) as u32 * 0x4000;
+    let screen_base = control.bits(8..13) as u32 * 0x800;
+    let wraparound = control.bit(13);
+    let size = 128i32 << control.bits(14..16);
+    let affine = &ppu.affine[bg - 2];
+    let origin_x = affine.internal_x - affine.pb as i32 * lines_back as i32;
+    let origin_y = affine.internal_y - affine.pd as i32 * lines_back as i32;
+
+    for (x, pixel) in pixels.iter_mut().enumerate().take(WIDTH) {
+        let texture_x = (origin_x + affine.pa as i32 * x as i32) >> 8;
+        let texture_y = (origin_y + affine.pc as i32 * x as i32) >> 8;
+
+        let (texture_x, texture_y) = if wraparound {
+            (texture_x & (size - 1), texture_y & (size - 1))
+        } else if texture_x < 0 || texture_x >= size || texture_y < 0
+            || texture_y >= size
+        {
+            *pixel = None;
+            continue;
+        } else {
+            (texture_x, texture_y)
+        };
+        let (texture_x, texture_y) = (texture_x as u32, texture_y as u32);
+
+        let map_index = (texture_y / 8) * (size as u32 / 8) + texture_x / 8;
+        let tile = ppu.vram.read_byte(screen_base + map_index) as u32;
+        let address =
+            char_base + tile * 64 + (texture_y % 8) * 8 + texture_x % 8;
+        *pixel = tile_color_256(ppu, address);
+    }
+}
+
+// Look up the colour of an 8bpp tile pixel, where index 0 is transparent.
+fn tile_color_256(ppu: &Ppu, address: u32) -> Option<u16> {
+    if address >= BG_VRAM_SIZE {
+        return None;
+    }
+    match ppu.vram.read_byte(address) {
+        0 => None,
+        index => Some(ppu.palette_color(index as u32)),
+    }
+}
+
+// Look up the colour of a 4bpp tile pixel in one of the 16 colour palette
+// banks, where index 0 is transparent.
+fn tile_color_16(
+    ppu: &Ppu,
+    address: u32,
+    high_nibble: bool,
+    palette_bank: u32,
+) -> Option<u16> {
+    if address >= BG_VRAM_SIZE {
+        return None;
+    }
+    let byte = ppu.vram.read_byte(address);
+    let index = if high_nibble { byte >> 4 } else { byte & 0xF };
+    match index {
+        0 => None,
+        index => Some(ppu.palette_color(palette_bank * 16 + index as u32)),
+    }
+}
@@ -0,0 +1,48 @@
+use bit::Bit;
+use bus::Read;
+use ppu::{Ppu, WIDTH};
+
+// Modes 4 and 5 have two frames, selected by DISPCNT bit 4
+fn frame_base(ppu: &Ppu) -> u32 {
+    if ppu.dispcnt.bit(4) {
+        0xA000
+    } else {
+        0
+    }
+}
+
+/// A single 240x160 frame of direct colours.
+pub fn render_mode_3(ppu: &Ppu, line: usize, pixels: &mut [Option<u16>]) {
+    for (x, pixel) in pixels.iter_mut().enumerate().take(WIDTH) {
+        let address = ((line * WIDTH + x) * 2) as u32;
+        *pixel = Some(ppu.vram.read_halfword(address));
+    }
+}
+
+/// Two 240x160 frames of palette indices, with index 0 transparent.
+pub fn render_mode_4(ppu: &Ppu, line: usize, pixels: &mut [Option<u16>]) {
+    let base = frame_base(ppu);
+    for (x, pixel) in pixels.iter_mut().enumerate().take(WIDTH) {
+        let address = base + (line * WIDTH + x) as u32;
+        let index = ppu.vram.read_byte(address) as u32;
+        if index != 0 {
+            *pixel = Some(ppu.palette_color(index));
+        }
+    }
+}
+
+/// Two 160x128 frames of direct colours. The rest of the screen shows the
+/// backdrop.
+pub fn render_mode_5(ppu: &Ppu, line: usize, pixels: &mut [Option<u16>]) {
+    const FRAME_WIDTH: usize = 160;
+    const FRAME_HEIGHT: usize = 128;
+
+    if line >= FRAME_HEIGHT {
+        return;
+    }
+    let base = frame_base(ppu);
+    for (x, pixel) in pixels.iter_mut().enumerate().take(FRAME_WIDTH) {
+        let address = base + ((line * FRAME_WIDTH + x) * 2) as u32;
+        *pixel = Some(ppu.vram.read_halfword(address));
+    }
+}
@@ -0,0 +1,36 @@
+use bit::Bits;
+
+/// Blend two colours by the coefficients in BLDALPHA, EVA for the first
+/// target and EVB for the second, each in units of 1/16 up to 16/16.
+pub fn alpha(first: u16, second: u16, bldalpha: u16) -> u16 {
+    let eva = bldalpha.bits(0..5).min(16) as u32;
+    let evb = bldalpha.bits(8..13).min(16) as u32;
+    map_channels(first, second, |a, b| (a * eva + b * evb) / 16)
+}
+
+/// Increase the brightness of a colour by EVY/16 of the way to white.
+pub fn brighten(color: u16, bldy: u16) -> u16 {
+    let evy = bldy.bits(0..5).min(16) as u32;
+    map_channels(color, color, |a, _| a + (31 - a) * evy / 16)
+}
+
+/// Decrease the brightness of a colour by EVY/16 of the way to black.
+pub fn darken(color: u16, bldy: u16) -> u16 {
+    let evy = bldy.bits(0..5).min(16) as u32;
+    map_channels(color, color, |a, _| a - a * evy / 16)
+}
+
+// Apply a function to each pair of 5 bit channels of two BGR555 colours,
+// saturating the result.
+fn map_channels<F>(first: u16, second: u16, f: F) -> u16
+where
+    F: Fn(u32, u32) -> u32,
+{
+    let mut color = 0;
+    for &shift in &[0, 5, 10] {
+        let a = first.bits(shift..shift + 5) as u32;
+        let b = second.bits(shift..shift + 5) as u32;
+        color |= (f(a, b).min(31) as u16) << shift;
+    }
+    color
+}
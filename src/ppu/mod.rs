@@ -0,0 +1,603 @@
+use bit::{Bit, Bits, SetBit, SetBits};
+use bus::{Read, Write};
+use interrupt_controller::{Input, InterruptController};
+use ram::Ram;
+use scheduler::{Event, Scheduler};
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+use video::VideoSink;
+
+mod background;
+mod bitmap;
+mod blend;
+mod object;
+mod window;
+
+use self::background::AffineParameters;
+
+pub const WIDTH: usize = 240;
+pub const HEIGHT: usize = 160;
+
+// Each line is drawn for 1006 cycles followed by an H-Blank of 226 cycles,
+// and the visible lines are followed by a V-Blank of 68 lines
+const HDRAW_CYCLES: u64 = 1006;
+const HBLANK_CYCLES: u64 = 226;
+const LINES: u16 = 228;
+
+/// The picture processing unit. It owns the LCD I/O registers, palette RAM,
+/// VRAM and OAM, and renders the display a line at a time into a framebuffer.
+///
+/// All of its memory is accessed by full address, so one device covers the
+/// I/O registers and each of the display memories.
+pub struct Ppu {
+    dispcnt: u16,
+    dispstat: u16,
+    vcount: u16,
+    bgcnt: [u16; 4],
+    bghofs: [u16; 4],
+    bgvofs: [u16; 4],
+    // Parameters of BG2 and BG3
+    affine: [AffineParameters; 2],
+    winh: [u16; 2],
+    winv: [u16; 2],
+    winin: u16,
+    winout: u16,
+    mosaic: u16,
+    bldcnt: u16,
+    bldalpha: u16,
+    bldy: u16,
+    palette: Ram,
+    vram: Ram,
+    oam: Ram,
+    framebuffer: Vec<u32>,
+    // Frames completed since the system was started
+    frames: u64,
+    sink: Option<Box<VideoSink>>,
+    interrupts: Rc<RefCell<InterruptController>>,
+    scheduler: Rc<RefCell<Scheduler>>,
+}
+
+impl Ppu {
+    /// Create a PPU at the start of the first line, scheduling the end of
+    /// its drawing.
+    pub fn new(
+        interrupts: Rc<RefCell<InterruptController>>,
+        scheduler: Rc<RefCell<Scheduler>>,
+    ) -> Ppu {
+        scheduler.borrow_mut().schedule(HDRAW_CYCLES, Event::HBlank);
+        Ppu {
+            dispcnt: 0,
+            dispstat: 0,
+            vcount: 0,
+            bgcnt: [0; 4],
+            bghofs: [0; 4],
+            bgvofs: [0; 4],
+            affine: [AffineParameters::new(), AffineParameters::new()],
+            winh: [0; 2],
+            winv: [0; 2],
+            winin: 0,
+            winout: 0,
+            mosaic: 0,
+            bldcnt: 0,
+            bldalpha: 0,
+            bldy: 0,
+            palette: Ram::new(0x400),
+            vram: Ram::new(0x20000),
+            oam: Ram::new(0x400),
+            framebuffer: vec![0; WIDTH * HEIGHT],
+            frames: 0,
+            sink: None,
+            interrupts: interrupts,
+            scheduler: scheduler,
+        }
+    }
+
+    /// The line being drawn, or the line of the V-Blank.
+    pub fn vcount(&self) -> u16 {
+        self.vcount
+    }
+
+    /// The rendered display, as rows of 0xRRGGBB pixels.
+    pub fn framebuffer(&self) -> &[u32] {
+        &self.framebuffer
+    }
+
+    /// The number of frames completed, each at the start of its V-Blank.
+    pub fn frame_count(&self) -> u64 {
+        self.frames
+    }
+
+    /// Send each frame completed from now on to a sink.
+    pub fn set_video_sink(&mut self, sink: Box<VideoSink>) {
+        self.sink = Some(sink);
+    }
+
+    /// The first error the video sink has met, if any.
+    pub fn video_error(&self) -> Option<&io::Error> {
+        self.sink.as_ref().and_then(|sink| sink.error())
+    }
+
+    /// Finish the video sink's output.
+    pub fn finish_video(&mut self) -> io::Result<()> {
+        match self.sink {
+            Some(ref mut sink) => sink.finish(),
+            None => Ok(()),
+        }
+    }
+
+    /// Handle one of the display's events, which was due at `time`. Each
+    /// visible line is rendered as its H-Blank begins.
+    pub fn handle_event(&mut self, event: Event, time: u64) {
+        match event {
+            Event::HBlank => {
+                self.start_hblank();
+                self.scheduler
+                    .borrow_mut()
+                    .schedule_at(time + HBLANK_CYCLES, Event::EndOfLine);
+            }
+            Event::EndOfLine => {
+                let line = (self.vcount + 1) % LINES;
+                self.start_line(line);
+                self.scheduler
+                    .borrow_mut()
+                    .schedule_at(time + HDRAW_CYCLES, Event::HBlank);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn start_hblank(&mut self) {
+        self.dispstat.set_bit(1, true);
+        if (self.vcount as usize) < HEIGHT {
+            let line = self.vcount as usize;
+            self.render_line(line);
+        }
+        if self.dispstat.bit(4) {
+            self.interrupts.borrow_mut().assert(Input::HBlank);
+        }
+    }
+
+    fn start_line(&mut self, line: u16) {
+        self.vcount = line;
+        self.dispstat.set_bit(1, false);
+
+        // The V-Blank flag is clear on the last line
+        if line == HEIGHT as u16 {
+            self.frames += 1;
+            if let Some(ref mut sink) = self.sink {
+                sink.push_frame(&self.framebuffer);
+            }
+            self.dispstat.set_bit(0, true);
+            // The affine reference points are reloaded for the next frame
+            for affine in self.affine.iter_mut() {
+                affine.reload();
+            }
+            if self.dispstat.bit(3) {
+                self.interrupts.borrow_mut().assert(Input::VBlank);
+            }
+        } else if line == LINES - 1 {
+            self.dispstat.set_bit(0, false);
+        }
+
+        let vcount_match = line == self.dispstat.bits(8..16);
+        self.dispstat.set_bit(2, vcount_match);
+        if vcount_match && self.dispstat.bit(5) {
+            self.interrupts.borrow_mut().assert(Input::VCounter);
+        }
+    }
+
+    // Render a single line of the display into the framebuffer.
+    fn render_line(&mut self, line: usize) {
+        let mut pixels = [self.backdrop(); WIDTH];
+
+        if self.dispcnt.bit(7) {
+            // Forced blank displays white
+            pixels = [0x7FFF; WIDTH];
+        } else {
+            let (layers, obj_window) = self.render_layers(line);
+            let enables = window::line_enables(self, line, &obj_window);
+            for (x, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = self.composite(&layers, x, enables[x]);
+            }
+        }
+
+        let row = &mut self.framebuffer[line * WIDTH..(line + 1) * WIDTH];
+        for (output, &color) in row.iter_mut().zip(pixels.iter()) {
+            *output = rgb(color);
+        }
+
+        for affine in self.affine.iter_mut() {
+            affine.advance();
+        }
+    }
+
+    // Render the objects followed by each enabled background of the current
+    // mode, along with the extent of the OBJ window.
+    fn render_layers(&self, line: usize) -> (Vec<Layer>, [bool; WIDTH]) {
+        let mut layers = Vec::new();
+        let mut obj_window = [false; WIDTH];
+
+        if self.dispcnt.bit(12) {
+            let mut pixels = [None; WIDTH];
+            object::render_objects(self, line, &mut pixels, &mut obj_window);
+            layers.push(Layer {
+                id: OBJ,
+                pixels: pixels,
+            });
+        }
+
+        for bg in 0..4 {
+            if !self.dispcnt.bit(8 + bg as u8) {
+                continue;
+            }
+
+            // Mosaic backgrounds repeat the top left pixel of each block
+            let (mosaic_width, mosaic_height) = if self.bgcnt[bg].bit(6) {
+                (
+                    self.mosaic.bits(0..4) as usize + 1,
+                    self.mosaic.bits(4..8) as usize + 1,
+                )
+            } else {
+                (1, 1)
+            };
+            let lines_back = line % mosaic_height;
+            let line = line - lines_back;
+
+            let mut colors = [None; WIDTH];
+            match (self.mode(), bg) {
+                (0, _) | (1, 0) | (1, 1) => {
+                    background::render_text(self, bg, line, &mut colors)
+                }
+                (1, 2) | (2, 2) | (2, 3) => background::render_affine(
+                    self,
+                    bg,
+                    lines_back as u32,
+                    &mut colors,
+                ),
+                (3, 2) => bitmap::render_mode_3(self, line, &mut colors),
+                (4, 2) => bitmap::render_mode_4(self, line, &mut colors),
+                (5, 2) => bitmap::render_mode_5(self, line, &mut colors),
+                _ => continue,
+            }
+            for block in colors.chunks_mut(mosaic_width) {
+                let first = block[0];
+                for color in block.iter_mut() {
+                    *color = first;
+                }
+            }
+
+            let priority = self.bgcnt[bg].bits(0..2);
+            let mut pixels = [None; WIDTH];
+            for (pixel, color) in pixels.iter_mut().zip(colors.iter()) {
+                *pixel = color.map(|color| Pixel {
+                    color: color,
+                    priority: priority,
+                    semi_transparent: false,
+                });
+            }
+            layers.push(Layer {
+                id: bg,
+                pixels: pixels,
+            });
+        }
+
+        (layers, obj_window)
+    }
+
+    // Find the colour shown at a point, from the topmost opaque pixel of the
+    // layers enabled by the windows and the pixel beneath it.
+    fn composite(&self, layers: &[Layer], x: usize, enables: u16) -> u16 {
+        let backdrop = (
+            BACKDROP,
+            Pixel {
+                color: self.backdrop(),
+                priority: 4,
+                semi_transparent: false,
+            },
+        );
+
+        // The layers are in order of precedence among equal priorities, so a
+        // pixel only displaces one of strictly lower priority
+        let mut top = backdrop;
+        let mut below = backdrop;
+        for layer in layers {
+            if !enables.bit(layer.id as u8) {
+                continue;
+            }
+            if let Some(pixel) = layer.pixels[x] {
+                if pixel.priority < top.1.priority {
+                    below = top;
+                    top = (layer.id, pixel);
+                } else if pixel.priority < below.1.priority {
+                    below = (layer.id, pixel);
+                }
+            }
+        }
+
+        let (top_id, pixel) = top;
+        let (below_id, below_pixel) = below;
+        if !enables.bit(5) {
+            return pixel.color;
+        }
+
+        let first_target = self.bldcnt.bit(top_id as u8);
+        let second_target = self.bldcnt.bit(8 + below_id as u8);
+        // Semi-transparent objects are blended with the layer beneath if it
+        // is a second target, whatever the blend mode
+        if pixel.semi_transparent && second_target {
+            return blend::alpha(pixel.color, below_pixel.color, self.bldalpha);
+        }
+
+        match self.bldcnt.bits(6..8) {
+            1 if first_target && second_target => {
+                blend::alpha(pixel.color, below_pixel.color, self.bldalpha)
+            }
+            2 if first_target => blend::brighten(pixel.color, self.bldy),
+            3 if first_target => blend::darken(pixel.color, self.bldy),
+            _ => pixel.color,
+        }
+    }
+
+    fn mode(&self) -> u16 {
+        self.dispcnt.bits(0..3)
+    }
+
+    // The colour shown where no layer has an opaque pixel
+    fn backdrop(&self) -> u16 {
+        self.palette_color(0)
+    }
+
+    fn palette_color(&self, index: u32) -> u16 {
+        self.palette.read_halfword(index * 2)
+    }
+
+    // Byte writes to BG VRAM write the byte to both halves of the halfword.
+    // Byte writes to OBJ VRAM are ignored. The boundary is moved up in the
+    // bitmap modes to make room for the larger bitmaps.
+    fn write_vram_byte(&mut self, address: u32, value: u8) {
+        let offset = vram_offset(address);
+        let obj_vram = if self.mode() >= 3 { 0x14000 } else { 0x10000 };
+        if offset < obj_vram {
+            let value = (value as u16) << 8 | value as u16;
+            self.vram.write_halfword(offset, value);
+        }
+    }
+
+    // LCD I/O registers, accessed as halfwords
+
+    // Registers not listed are write-only or unused, and read as zero.
+    fn read_register(&self, address: u32) -> u16 {
+        match address & 0xFF {
+            0x00 => self.dispcnt,
+            0x04 => self.dispstat,
+            0x06 => self.vcount,
+            0x08 => self.bgcnt[0],
+            0x0A => self.bgcnt[1],
+            0x0C => self.bgcnt[2],
+            0x0E => self.bgcnt[3],
+            0x48 => self.winin,
+            0x4A => self.winout,
+            0x50 => self.bldcnt,
+            0x52 => self.bldalpha,
+            _ => 0,
+        }
+    }
+
+    // The value last written to a register, including those which are
+    // write-only, for a byte write to be merged with
+    fn written_register(&self, address: u32) -> u16 {
+        match address & 0xFF {
+            0x10...0x1F => {
+                let bg = (address as usize & 0xF) / 4;
+                if address & 2 == 0 {
+                    self.bghofs[bg]
+                } else {
+                    self.bgvofs[bg]
+                }
+            }
+            0x20...0x3F => {
+                let affine = &self.affine[(address as usize & 0x1F) / 0x10];
+                match address & 0xF {
+                    0x0 => affine.pa as u16,
+                    0x2 => affine.pb as u16,
+                    0x4 => affine.pc as u16,
+                    0x6 => affine.pd as u16,
+                    0x8 => affine.x(false),
+                    0xA => affine.x(true),
+                    0xC => affine.y(false),
+                    _ => affine.y(true),
+                }
+            }
+            0x40 => self.winh[0],
+            0x42 => self.winh[1],
+            0x44 => self.winv[0],
+            0x46 => self.winv[1],
+            0x4C => self.mosaic,
+            0x54 => self.bldy,
+            _ => self.read_register(address),
+        }
+    }
+
+    fn write_register(&mut self, address: u32, value: u16) {
+        match address & 0xFF {
+            // Bit 3 is set only by the boot ROM of a GBC
+            0x00 => self.dispcnt = value & !0x8,
+            // The status flags are read-only
+            0x04 => self.dispstat.set_bits(3..16, value >> 3),
+            // The wraparound bit only exists for BG2 and BG3
+            0x08 => self.bgcnt[0] = value & !0x2000,
+            0x0A => self.bgcnt[1] = value & !0x2000,
+            0x0C => self.bgcnt[2] = value,
+            0x0E => self.bgcnt[3] = value,
+            0x10 => self.bghofs[0] = value & 0x1FF,
+            0x12 => self.bgvofs[0] = value & 0x1FF,
+            0x14 => self.bghofs[1] = value & 0x1FF,
+            0x16 => self.bgvofs[1] = value & 0x1FF,
+            0x18 => self.bghofs[2] = value & 0x1FF,
+            0x1A => self.bgvofs[2] = value & 0x1FF,
+            0x1C => self.bghofs[3] = value & 0x1FF,
+            0x1E => self.bgvofs[3] = value & 0x1FF,
+            0x20 => self.affine[0].pa = value as i16,
+            0x22 => self.affine[0].pb = value as i16,
+            0x24 => self.affine[0].pc = value as i16,
+            0x26 => self.affine[0].pd = value as i16,
+            0x28 => self.affine[0].set_x(false, value),
+            0x2A => self.affine[0].set_x(true, value),
+            0x2C => self.affine[0].set_y(false, value),
+            0x2E => self.affine[0].set_y(true, value),
+            0x30 => self.affine[1].pa = value as i16,
+            0x32 => self.affine[1].pb = value as i16,
+            0x34 => self.affine[1].pc = value as i16,
+            0x36 => self.affine[1].pd = value as i16,
+            0x38 => self.affine[1].set_x(false, value),
+            0x3A => self.affine[1].set_x(true, value),
+            0x3C => self.affine[1].set_y(false, value),
+            0x3E => self.affine[1].set_y(true, value),
+            0x40 => self.winh[0] = value,
+            0x42 => self.winh[1] = value,
+            0x44 => self.winv[0] = value,
+            0x46 => self.winv[1] = value,
+            0x48 => self.winin = value & 0x3F3F,
+            0x4A => self.winout = value & 0x3F3F,
+            0x4C => self.mosaic = value,
+            0x50 => self.bldcnt = value & 0x3FFF,
+            0x52 => self.bldalpha = value & 0x1F1F,
+            0x54 => self.bldy = value & 0x1F,
+            _ => {}
+        }
+    }
+}
+
+impl Read for Ppu {
+    fn read_byte(&self, address: u32) -> u8 {
+        match address {
+            0x4000000...0x4FFFFFF => {
+                let value = self.read_register(address & !1);
+                (value >> (8 * (address & 1))) as u8
+            }
+            0x5000000...0x5FFFFFF => self.palette.read_byte(address),
+            0x6000000...0x6FFFFFF => {
+                self.vram.read_byte(vram_offset(address))
+            }
+            0x7000000...0x7FFFFFF => self.oam.read_byte(address),
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        match address {
+            0x4000000...0x4FFFFFF => self.read_register(address),
+            0x5000000...0x5FFFFFF => self.palette.read_halfword(address),
+            0x6000000...0x6FFFFFF => {
+                self.vram.read_halfword(vram_offset(address))
+            }
+            0x7000000...0x7FFFFFF => self.oam.read_halfword(address),
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        match address {
+            0x4000000...0x4FFFFFF => {
+                let low = self.read_register(address) as u32;
+                let high = self.read_register(address + 2) as u32;
+                high << 16 | low
+            }
+            0x5000000...0x5FFFFFF => self.palette.read_word(address),
+            0x6000000...0x6FFFFFF => self.vram.read_word(vram_offset(address)),
+            0x7000000...0x7FFFFFF => self.oam.read_word(address),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Write for Ppu {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        match address {
+            0x4000000...0x4FFFFFF => {
+                let mut halfword = self.written_register(address & !1);
+                let shift = 8 * (address & 1) as u8;
+                halfword.set_bits(shift..shift + 8, value as u16);
+                self.write_register(address & !1, halfword);
+            }
+            // Byte writes to palette RAM write the byte to both halves of the
+            // halfword
+            0x5000000...0x5FFFFFF => {
+                let value = (value as u16) << 8 | value as u16;
+                self.palette.write_halfword(address, value);
+            }
+            0x6000000...0x6FFFFFF => self.write_vram_byte(address, value),
+            // Byte writes to OAM are ignored
+            0x7000000...0x7FFFFFF => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        match address {
+            0x4000000...0x4FFFFFF => self.write_register(address, value),
+            0x5000000...0x5FFFFFF => {
+                self.palette.write_halfword(address, value)
+            }
+            0x6000000...0x6FFFFFF => {
+                self.vram.write_halfword(vram_offset(address), value)
+            }
+            0x7000000...0x7FFFFFF => self.oam.write_halfword(address, value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        match address {
+            0x4000000...0x4FFFFFF => {
+                self.write_register(address, value as u16);
+                self.write_register(address + 2, (value >> 16) as u16);
+            }
+            0x5000000...0x5FFFFFF => self.palette.write_word(address, value),
+            0x6000000...0x6FFFFFF => {
+                self.vram.write_word(vram_offset(address), value)
+            }
+            0x7000000...0x7FFFFFF => self.oam.write_word(address, value),
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Layer IDs, numbered as in the target bits of BLDCNT. Backgrounds are
+// numbered 0-3.
+const OBJ: usize = 4;
+const BACKDROP: usize = 5;
+
+/// A line of a background or of the objects, to be composited.
+struct Layer {
+    id: usize,
+    pixels: [Option<Pixel>; WIDTH],
+}
+
+#[derive(Clone, Copy)]
+struct Pixel {
+    color: u16,
+    priority: u16,
+    semi_transparent: bool,
+}
+
+// VRAM is 96 KiB, mirrored every 128 KiB with the last 32 KiB mirroring the
+// 32 KiB before it.
+fn vram_offset(address: u32) -> u32 {
+    let offset = address & 0x1FFFF;
+    if offset >= 0x18000 {
+        offset - 0x8000
+    } else {
+        offset
+    }
+}
+
+// Convert a BGR555 colour to 0xRRGGBB, scaling each channel to 8 bits.
+fn rgb(color: u16) -> u32 {
+    let channel = |range| {
+        let value = color.bits(range) as u32;
+        value << 3 | value >> 2
+    };
+    channel(0..5) << 16 | channel(5..10) << 8 | channel(10..15)
+}
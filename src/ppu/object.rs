@@ -0,0 +1,275 @@
+use bit::{Bit, Bits};
+use bus::Read;
+use ppu::{Pixel, Ppu, WIDTH};
+
+// Object tiles are stored after the background tiles in VRAM
+const OBJ_VRAM_BASE: u32 = 0x10000;
+
+// The number of cycles available to render objects on each line, reduced if
+// OAM can be accessed during H-Blank
+const LINE_CYCLES: i32 = 1210;
+const LINE_CYCLES_HBLANK_FREE: i32 = 954;
+
+/// The attributes of one of the 128 objects in OAM.
+struct Object {
+    attr0: u16,
+    attr1: u16,
+    attr2: u16,
+}
+
+impl Object {
+    fn read(ppu: &Ppu, index: u32) -> Object {
+        let address = index * 8;
+        Object {
+            attr0: ppu.oam.read_halfword(address),
+            attr1: ppu.oam.read_halfword(address + 2),
+            attr2: ppu.oam.read_halfword(address + 4),
+        }
+    }
+
+    fn is_affine(&self) -> bool {
+        self.attr0.bit(8)
+    }
+
+    // For affine objects bit 9 doubles the size of the bounding box, which
+    // prevents the corners being clipped when rotated
+    fn is_disabled(&self) -> bool {
+        !self.is_affine() && self.attr0.bit(9)
+    }
+
+    fn is_double_size(&self) -> bool {
+        self.is_affine() && self.attr0.bit(9)
+    }
+
+    fn mode(&self) -> u16 {
+        self.attr0.bits(10..12)
+    }
+
+    fn colors_256(&self) -> bool {
+        self.attr0.bit(13)
+    }
+
+    fn y(&self) -> u32 {
+        self.attr0.bits(0..8) as u32
+    }
+
+    // X is a signed 9 bit value
+    fn x(&self) -> i32 {
+        ((self.attr1.bits(0..9) as i32) << 23) >> 23
+    }
+
+    fn size(&self) -> (u32, u32) {
+        match (self.attr0.bits(14..16), self.attr1.bits(14..16)) {
+            (0, 0) => (8, 8),
+            (0, 1) => (16, 16),
+            (0, 2) => (32, 32),
+            (0, 3) => (64, 64),
+            (1, 0) => (16, 8),
+            (1, 1) => (32, 8),
+            (1, 2) => (32, 16),
+            (1, 3) => (64, 32),
+            (2, 0) => (8, 16),
+            (2, 1) => (8, 32),
+            (2, 2) => (16, 32),
+            (2, 3) => (32, 64),
+            // The fourth shape is prohibited
+            _ => (8, 8),
+        }
+    }
+
+    fn tile(&self) -> u32 {
+        self.attr2.bits(0..10) as u32
+    }
+
+    fn priority(&self) -> u16 {
+        self.attr2.bits(10..12)
+    }
+
+    fn palette_bank(&self) -> u32 {
+        self.attr2.bits(12..16) as u32
+    }
+}
+
+/// Render the objects on a line. Where objects overlap, the object with the
+/// highest priority is shown, then the object earliest in OAM.
+///
+/// Objects in OBJ window mode aren't displayed, instead marking the points
+/// inside the OBJ window in `window`.
+pub fn render_objects(
+    ppu: &Ppu,
+    line: usize,
+    pixels: &mut [Option<Pixel>],
+    window: &mut [bool],
+) {
+    let one_dimensional = ppu.dispcnt.bit(6);
+    let mut cycles = if ppu.dispcnt.bit(5) {
+        LINE_CYCLES_HBLANK_FREE
+    } else {
+        LINE_CYCLES
+    };
+
+    for index in 0..128 {
+        let object = Object::read(ppu, index);
+        // Mode 3 is prohibited
+        if object.is_disabled() || object.mode() == 3 {
+            continue;
+        }
+
+        let (width, height) = object.size();
+        let (box_width, box_height) = if object.is_double_size() {
+            (width * 2, height * 2)
+        } else {
+            (width, height)
+        };
+
+        // The Y coordinate wraps at the bottom of the 256 line space
+        let box_y = (line as u32).wrapping_sub(object.y()) & 0xFF;
+        if box_y >= box_height {
+            continue;
+        }
+
+        // Objects are drawn until the line's rendering time runs out
+        cycles -= if object.is_affine() {
+            10 + 2 * box_width as i32
+        } else {
+            width as i32
+        };
+        if cycles < 0 {
+            break;
+        }
+
+        // Mosaic objects sample the top left pixel of each mosaic block
+        let (mosaic_width, mosaic_height) = if object.attr0.bit(12) {
+            (
+                ppu.mosaic.bits(8..12) as u32 + 1,
+                ppu.mosaic.bits(12..16) as u32 + 1,
+            )
+        } else {
+            (1, 1)
+        };
+        let box_y = box_y.saturating_sub(line as u32 % mosaic_height);
+
+        let affine = if object.is_affine() {
+            let group = object.attr1.bits(9..14) as u32 * 32;
+            let parameter = |offset| ppu.oam.read_halfword(group + offset);
+            Some((
+                parameter(6) as i16 as i32,
+                parameter(14) as i16 as i32,
+                parameter(22) as i16 as i32,
+                parameter(30) as i16 as i32,
+            ))
+        } else {
+            None
+        };
+
+        for box_x in 0..box_width {
+            let screen_x = object.x() + box_x as i32;
+            if screen_x < 0 || screen_x >= WIDTH as i32 {
+                continue;
+            }
+            let box_x = box_x.saturating_sub(screen_x as u32 % mosaic_width);
+
+            let (texture_x, texture_y) = match affine {
+                Some((pa, pb, pc, pd)) => {
+                    // Rotate about the centre of the bounding box
+                    let dx = box_x as i32 - box_width as i32 / 2;
+                    let dy = box_y as i32 - box_height as i32 / 2;
+                    let texture_x =
+                        ((pa * dx + pb * dy) >> 8) + width as i32 / 2;
+                    let texture_y =
+                        ((pc * dx + pd * dy) >> 8) + height as i32 / 2;
+                    if texture_x < 0 || texture_x >= width as i32
+                        || texture_y < 0
+                        || texture_y >= height as i32
+                    {
+                        continue;
+                    }
+                    (texture_x as u32, texture_y as u32)
+                }
+                None => {
+                    let mut texture_x = box_x;
+                    let mut texture_y = box_y;
+                    if object.attr1.bit(12) {
+                        texture_x = width - 1 - texture_x;
+                    }
+                    if object.attr1.bit(13) {
+                        texture_y = height - 1 - texture_y;
+                    }
+                    (texture_x, texture_y)
+                }
+            };
+
+            let color = match pixel_color(
+                ppu,
+                &object,
+                texture_x,
+                texture_y,
+                one_dimensional,
+            ) {
+                Some(color) => color,
+                None => continue,
+            };
+
+            if object.mode() == 2 {
+                window[screen_x as usize] = true;
+                continue;
+            }
+
+            let pixel = &mut pixels[screen_x as usize];
+            let covered = match *pixel {
+                Some(existing) => existing.priority <= object.priority(),
+                None => false,
+            };
+            if !covered {
+                *pixel = Some(Pixel {
+                    color: color,
+                    priority: object.priority(),
+                    semi_transparent: object.mode() == 1,
+                });
+            }
+        }
+    }
+}
+
+// Look up the colour of a pixel of an object's tiles, where index 0 is
+// transparent.
+fn pixel_color(
+    ppu: &Ppu,
+    object: &Object,
+    x: u32,
+    y: u32,
+    one_dimensional: bool,
+) -> Option<u16> {
+    // Tiles are numbered in 32 byte units, so 8bpp tiles take two numbers
+    let tile_units = if object.colors_256() { 2 } else { 1 };
+    let row_units = if one_dimensional {
+        object.size().0 / 8 * tile_units
+    } else {
+        32
+    };
+    let tile = object.tile() + (y / 8) * row_units + (x / 8) * tile_units;
+
+    // In the bitmap modes the first half of OBJ VRAM holds the bitmap
+    if ppu.mode() >= 3 && tile & 0x3FF < 512 {
+        return None;
+    }
+
+    let tile_address = OBJ_VRAM_BASE + (tile & 0x3FF) * 32;
+    let (x, y) = (x % 8, y % 8);
+    let index = if object.colors_256() {
+        ppu.vram.read_byte(tile_address + y * 8 + x) as u32
+    } else {
+        let byte = ppu.vram.read_byte(tile_address + y * 4 + x / 2);
+        let nibble = if x.bit(0) { byte >> 4 } else { byte & 0xF };
+        match nibble {
+            0 => 0,
+            nibble => object.palette_bank() * 16 + nibble as u32,
+        }
+    };
+
+    // Objects use the second half of palette RAM
+    match index {
+        0 => None,
+        index => Some(ppu.palette_color(256 + index)),
+    }
+}
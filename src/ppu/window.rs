@@ -0,0 +1,51 @@
+use bit::{Bit, Bits};
+use ppu::{Ppu, HEIGHT, WIDTH};
+
+// Every layer and effects enabled, as when no windows are in use
+const ALL_ENABLED: u16 = 0x3F;
+
+/// Find the layers and effects enabled at each point on a line, in the
+/// format of each half of WININ and WINOUT: bits 0-3 enable BG0-BG3, bit 4
+/// OBJ and bit 5 colour effects.
+///
+/// WIN0 takes precedence over WIN1, which takes precedence over the OBJ
+/// window. Everywhere else is outside the windows.
+pub fn line_enables(
+    ppu: &Ppu,
+    line: usize,
+    obj_window: &[bool],
+) -> [u16; WIDTH] {
+    let mut enables = [ALL_ENABLED; WIDTH];
+    if ppu.dispcnt.bits(13..16) == 0 {
+        return enables;
+    }
+
+    let win0 = ppu.dispcnt.bit(13) && contains(ppu.winv[0], line, HEIGHT);
+    let win1 = ppu.dispcnt.bit(14) && contains(ppu.winv[1], line, HEIGHT);
+    let obj = ppu.dispcnt.bit(15);
+
+    for x in 0..WIDTH {
+        enables[x] = if win0 && contains(ppu.winh[0], x, WIDTH) {
+            ppu.winin.bits(0..6)
+        } else if win1 && contains(ppu.winh[1], x, WIDTH) {
+            ppu.winin.bits(8..14)
+        } else if obj && obj_window[x] {
+            ppu.winout.bits(8..14)
+        } else {
+            ppu.winout.bits(0..6)
+        };
+    }
+    enables
+}
+
+// Window dimensions hold the first coordinate in the high byte and the
+// coordinate after the last in the low byte. An end beyond the screen or
+// before the start is treated as the edge of the screen.
+fn contains(dimension: u16, position: usize, limit: usize) -> bool {
+    let start = dimension.bits(8..16) as usize;
+    let mut end = dimension.bits(0..8) as usize;
+    if end > limit || start > end {
+        end = limit;
+    }
+    start <= position && position < end
+}
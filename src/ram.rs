@@ -0,0 +1,57 @@
+use bus::{Read, Write};
+use byteorder::{ByteOrder, LittleEndian};
+
+/// A block of RAM which is mirrored across any address range it is mapped
+/// into.
+///
+/// Accesses are aligned to the size of the access, as on the GBA's bus.
+pub struct Ram {
+    data: Vec<u8>,
+}
+
+impl Ram {
+    /// Create a zeroed block of RAM. `size` must be a power of two.
+    pub fn new(size: usize) -> Ram {
+        assert!(size.is_power_of_two());
+        Ram {
+            data: vec![0; size],
+        }
+    }
+
+    fn index(&self, address: u32) -> usize {
+        address as usize & (self.data.len() - 1)
+    }
+}
+
+impl Read for Ram {
+    fn read_byte(&self, address: u32) -> u8 {
+        self.data[self.index(address)]
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        let index = self.index(address & !1);
+        LittleEndian::read_u16(&self.data[index..])
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        let index = self.index(address & !3);
+        LittleEndian::read_u32(&self.data[index..])
+    }
+}
+
+impl Write for Ram {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        let index = self.index(address);
+        self.data[index] = value;
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        let index = self.index(address & !1);
+        LittleEndian::write_u16(&mut self.data[index..], value);
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        let index = self.index(address & !3);
+        LittleEndian::write_u32(&mut self.data[index..], value);
+    }
+}
@@ -0,0 +1,71 @@
+use bus::Read;
+
+const BIOS_SIZE: usize = 0x4000;
+const MAX_CARTRIDGE_SIZE: usize = 0x2000000;
+
+/// Read-only memory holding the BIOS or a game pak ROM.
+///
+/// Accesses are aligned to the size of the access, as on the GBA's bus.
+/// Reads beyond the end of the data return the value left on the game pak
+/// bus, which is the halfword address being read.
+pub struct Rom {
+    data: Vec<u8>,
+}
+
+impl Rom {
+    pub fn bios(data: Vec<u8>) -> Result<Rom, String> {
+        if data.len() != BIOS_SIZE {
+            return Err(format!(
+                "Expected a BIOS of {} bytes, found {} bytes",
+                BIOS_SIZE,
+                data.len()
+            ));
+        }
+        Ok(Rom { data: data })
+    }
+
+    pub fn cartridge(data: Vec<u8>) -> Result<Rom, String> {
+        if data.is_empty() {
+            return Err("ROM is empty".to_string());
+        }
+        if data.len() > MAX_CARTRIDGE_SIZE {
+            return Err(format!(
+                "Expected a ROM of at most {} bytes, found {} bytes",
+                MAX_CARTRIDGE_SIZE,
+                data.len()
+            ));
+        }
+        Ok(Rom { data: data })
+    }
+
+    fn byte(&self, address: u32) -> u8 {
+        match self.data.get(address as usize) {
+            Some(&byte) => byte,
+            None => {
+                let open_bus = (address >> 1) as u16;
+                if address & 1 == 0 {
+                    open_bus as u8
+                } else {
+                    (open_bus >> 8) as u8
+                }
+            }
+        }
+    }
+}
+
+impl Read for Rom {
+    fn read_byte(&self, address: u32) -> u8 {
+        self.byte(address)
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        let address = address & !1;
+        self.byte(address) as u16 | (self.byte(address + 1) as u16) << 8
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        let address = address & !3;
+        self.read_halfword(address) as u32
+            | (self.read_halfword(address + 2) as u32) << 16
+    }
+}
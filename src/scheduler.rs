@@ -0,0 +1,193 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+
+/// The things hardware components schedule to happen at a later cycle.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Event {
+    // The PPU finishes drawing a line
+    HBlank,
+    // The PPU moves on to the next line
+    EndOfLine,
+    // A timer's counter overflows
+    TimerOverflow(usize),
+    // The APU's frame sequencer clocks the channels' lengths, sweep and
+    // envelopes
+    FrameSequencer,
+    // The APU mixes its next sample
+    Sample,
+}
+
+/// A queue of events, each due at a cycle timestamp.
+///
+/// Components schedule their own events rather than being stepped every
+/// instruction, so time can be skipped to the next event when the CPU is
+/// idle. Events due at the same cycle are taken in the order they were
+/// scheduled, so the order of events is deterministic.
+pub struct Scheduler {
+    // Cycles since the system was started
+    now: u64,
+    queue: BinaryHeap<Reverse<Entry>>,
+    // Count of events scheduled, to order events due at the same cycle
+    sequence: u64,
+}
+
+struct Entry {
+    time: u64,
+    sequence: u64,
+    event: Event,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Entry) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Entry {}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Entry) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Entry {
+    fn cmp(&self, other: &Entry) -> Ordering {
+        (self.time, self.sequence).cmp(&(other.time, other.sequence))
+    }
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler {
+            now: 0,
+            queue: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// The current cycle timestamp.
+    pub fn now(&self) -> u64 {
+        self.now
+    }
+
+    /// Schedule an event a number of cycles from now.
+    pub fn schedule(&mut self, cycles: u64, event: Event) {
+        let time = self.now + cycles;
+        self.schedule_at(time, event);
+    }
+
+    /// Schedule an event at a cycle timestamp. Periodic events should be
+    /// rescheduled relative to the time they were due rather than now, so
+    /// they don't drift when they are handled late.
+    pub fn schedule_at(&mut self, time: u64, event: Event) {
+        self.queue.push(Reverse(Entry {
+            time: time,
+            sequence: self.sequence,
+            event: event,
+        }));
+        self.sequence += 1;
+    }
+
+    /// Remove every scheduled occurrence of an event.
+    pub fn cancel(&mut self, event: Event) {
+        let entries = self.queue.drain().filter(|entry| entry.0.event != event);
+        self.queue = entries.collect();
+    }
+
+    /// Move time forward by a number of cycles.
+    pub fn advance(&mut self, cycles: u64) {
+        self.now += cycles;
+    }
+
+    /// Take the next event which is due, with the timestamp it was due at.
+    pub fn pop_due(&mut self) -> Option<(Event, u64)> {
+        let due = match self.queue.peek() {
+            Some(entry) => entry.0.time <= self.now,
+            None => false,
+        };
+        if due {
+            self.queue.pop().map(|entry| (entry.0.event, entry.0.time))
+        } else {
+            None
+        }
+    }
+
+    /// The number of cycles until the next event is due, if any are
+    /// scheduled.
+    pub fn cycles_until_next(&self) -> Option<u64> {
+        self.queue
+            .peek()
+            .map(|entry| entry.0.time.saturating_sub(self.now))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn events_are_taken_in_time_order() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(30, Event::Sample);
+        scheduler.schedule(10, Event::HBlank);
+        scheduler.schedule(20, Event::EndOfLine);
+        scheduler.advance(30);
+        assert_eq!(scheduler.pop_due(), Some((Event::HBlank, 10)));
+        assert_eq!(scheduler.pop_due(), Some((Event::EndOfLine, 20)));
+        assert_eq!(scheduler.pop_due(), Some((Event::Sample, 30)));
+        assert_eq!(scheduler.pop_due(), None);
+    }
+
+    #[test]
+    fn events_at_the_same_time_are_taken_in_the_order_scheduled() {
+        let mut scheduler = Scheduler::new();
+        let events = [
+            Event::TimerOverflow(2),
+            Event::Sample,
+            Event::TimerOverflow(0),
+            Event::FrameSequencer,
+            Event::TimerOverflow(1),
+        ];
+        for &event in &events {
+            scheduler.schedule(5, event);
+        }
+        scheduler.advance(5);
+        for &event in &events {
+            assert_eq!(scheduler.pop_due(), Some((event, 5)));
+        }
+    }
+
+    #[test]
+    fn events_are_not_taken_before_they_are_due() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(10, Event::HBlank);
+        scheduler.advance(4);
+        assert_eq!(scheduler.cycles_until_next(), Some(6));
+        assert_eq!(scheduler.pop_due(), None);
+        scheduler.advance(6);
+        assert_eq!(scheduler.cycles_until_next(), Some(0));
+        assert_eq!(scheduler.pop_due(), Some((Event::HBlank, 10)));
+        assert_eq!(scheduler.cycles_until_next(), None);
+    }
+
+    #[test]
+    fn late_events_keep_the_time_they_were_due() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule_at(100, Event::Sample);
+        scheduler.advance(150);
+        assert_eq!(scheduler.pop_due(), Some((Event::Sample, 100)));
+    }
+
+    #[test]
+    fn cancel_removes_every_occurrence() {
+        let mut scheduler = Scheduler::new();
+        scheduler.schedule(10, Event::TimerOverflow(0));
+        scheduler.schedule(20, Event::TimerOverflow(1));
+        scheduler.schedule(30, Event::TimerOverflow(0));
+        scheduler.cancel(Event::TimerOverflow(0));
+        scheduler.advance(30);
+        assert_eq!(scheduler.pop_due(), Some((Event::TimerOverflow(1), 20)));
+        assert_eq!(scheduler.pop_due(), None);
+    }
+}
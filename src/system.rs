@@ -0,0 +1,212 @@
+use audio::AudioSink;
+use bios::{self, Hle};
+use cpu::{Cpu, Register};
+use interrupt_controller::InterruptController;
+use keypad::Key;
+use memory_map::MemoryMap;
+use rom::Rom;
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+use video::VideoSink;
+
+// Where the game pak's code starts, which the BIOS jumps to after booting
+const ROM_ENTRY: u32 = 0x8000000;
+
+/// A Game Boy Advance with a game pak inserted.
+///
+/// The system runs in steps of a CPU instruction or a whole frame. Between
+/// steps the frontend presses keys, reads the display and sound, and can
+/// inspect or change memory and registers.
+pub struct Gba {
+    cpu: Cpu,
+    // Whether the BIOS is a real one rather than the built-in stand-in
+    real_bios: bool,
+}
+
+impl Gba {
+    /// Create a system which boots through a BIOS image and then runs the
+    /// ROM.
+    pub fn new(bios: Vec<u8>, rom: Vec<u8>) -> Result<Gba, String> {
+        let bios = Rom::bios(bios)
+            .map_err(|err| format!("Error loading BIOS:\n  {}", err))?;
+        Gba::with_bios(bios, rom, true)
+    }
+
+    /// Create a system without the real BIOS, which starts straight at the
+    /// ROM's entry point with BIOS calls serviced natively.
+    pub fn with_hle_bios(rom: Vec<u8>) -> Result<Gba, String> {
+        let bios = Rom::bios(bios::image())
+            .map_err(|err| format!("Error loading BIOS:\n  {}", err))?;
+        let mut gba = Gba::with_bios(bios, rom, false)?;
+        gba.set_hle_bios(true);
+        bios::boot(&mut gba.cpu, ROM_ENTRY);
+        Ok(gba)
+    }
+
+    fn with_bios(
+        bios: Rom,
+        rom: Vec<u8>,
+        real_bios: bool,
+    ) -> Result<Gba, String> {
+        let rom = Rom::cartridge(rom)
+            .map_err(|err| format!("Error loading ROM:\n  {}", err))?;
+        let interrupts = Rc::new(RefCell::new(InterruptController::new()));
+        let memory = MemoryMap::new(bios, rom, Rc::clone(&interrupts));
+        Ok(Gba {
+            cpu: Cpu::new(memory, interrupts),
+            real_bios: real_bios,
+        })
+    }
+
+    /// Service BIOS calls natively rather than running the BIOS's code.
+    /// Calls which aren't emulated are still left to a real BIOS.
+    pub fn set_hle_bios(&mut self, enabled: bool) {
+        self.cpu.hle = if enabled {
+            Some(Hle::new(self.real_bios))
+        } else {
+            None
+        };
+    }
+
+    /// The BIOS functions the game has called which the native BIOS doesn't
+    /// emulate, and which had no real BIOS to run them. They return without
+    /// doing anything. Each is listed once, in the order first called.
+    pub fn unsupported_bios_functions(&self) -> &[u32] {
+        match self.cpu.hle {
+            Some(ref hle) => hle.unsupported(),
+            None => &[],
+        }
+    }
+
+    /// Run a single instruction, or while the CPU is halted, up to the next
+    /// event which could wake it.
+    pub fn step(&mut self) {
+        self.cpu.tick();
+    }
+
+    /// Run until the next frame is complete, at the start of its V-Blank.
+    /// The sound produced so far is passed to the audio sink, so that it has
+    /// everything up to the end of the frame.
+    pub fn run_frame(&mut self) {
+        let frame = self.frame_count();
+        while self.frame_count() == frame {
+            self.cpu.tick();
+        }
+        self.cpu.memory.apu_mut().flush_audio();
+    }
+
+    /// The number of frames completed since the system was started.
+    pub fn frame_count(&self) -> u64 {
+        self.cpu.memory.ppu().frame_count()
+    }
+
+    pub fn set_key(&mut self, key: Key, pressed: bool) {
+        let keypad = self.cpu.memory.keypad_mut();
+        if pressed {
+            keypad.press(key);
+        } else {
+            keypad.release(key);
+        }
+    }
+
+    /// The display, as `HEIGHT` rows of `WIDTH` 0xRRGGBB pixels.
+    pub fn framebuffer(&self) -> &[u32] {
+        self.cpu.memory.ppu().framebuffer()
+    }
+
+    /// Take the sound produced since it was last taken, as left and right
+    /// samples at `SAMPLE_RATE`. Sound is only kept while there is no audio
+    /// sink, and only the first second of it.
+    pub fn take_audio(&mut self) -> Vec<[i16; 2]> {
+        self.cpu.memory.apu_mut().take_frames()
+    }
+
+    /// Send the sound produced from now on to a sink, resampled to its rate.
+    pub fn set_audio_sink(&mut self, sink: Box<AudioSink>) {
+        self.cpu.memory.apu_mut().set_audio_sink(sink);
+    }
+
+    /// The first error the audio sink has met, if any, which
+    /// `finish_audio` will also return.
+    pub fn audio_error(&self) -> Option<&io::Error> {
+        self.cpu.memory.apu().audio_error()
+    }
+
+    /// Send the sound still buffered to the audio sink and finish its
+    /// output, returning the first error the sink met. Call this once no
+    /// more frames will be run.
+    pub fn finish_audio(&mut self) -> io::Result<()> {
+        self.cpu.memory.apu_mut().finish_audio()
+    }
+
+    /// Send each frame completed from now on to a sink.
+    pub fn set_video_sink(&mut self, sink: Box<VideoSink>) {
+        self.cpu.memory.ppu_mut().set_video_sink(sink);
+    }
+
+    /// The first error the video sink has met, if any, which `finish_video`
+    /// will also return.
+    pub fn video_error(&self) -> Option<&io::Error> {
+        self.cpu.memory.ppu().video_error()
+    }
+
+    /// Finish the video sink's output, returning the first error it met.
+    /// Call this once no more frames will be run.
+    pub fn finish_video(&mut self) -> io::Result<()> {
+        self.cpu.memory.ppu_mut().finish_video()
+    }
+
+    // Memory is accessed as the CPU would see it, but the accesses take no
+    // time. Addresses are aligned to the size of the access. Reads of
+    // unmapped memory give None, and writes to unmapped or read-only memory
+    // are ignored and return false.
+
+    pub fn read_byte(&self, address: u32) -> Option<u8> {
+        self.cpu.memory.debug_read_byte(address)
+    }
+
+    pub fn read_halfword(&self, address: u32) -> Option<u16> {
+        self.cpu.memory.debug_read_halfword(address)
+    }
+
+    pub fn read_word(&self, address: u32) -> Option<u32> {
+        self.cpu.memory.debug_read_word(address)
+    }
+
+    pub fn write_byte(&mut self, address: u32, value: u8) -> bool {
+        self.cpu.memory.debug_write_byte(address, value)
+    }
+
+    pub fn write_halfword(&mut self, address: u32, value: u16) -> bool {
+        self.cpu.memory.debug_write_halfword(address, value)
+    }
+
+    pub fn write_word(&mut self, address: u32, value: u32) -> bool {
+        self.cpu.memory.debug_write_word(address, value)
+    }
+
+    /// Read r0-r15 from the bank of the current mode, or None for any other
+    /// index.
+    pub fn register(&self, index: u32) -> Option<u32> {
+        if index > 15 {
+            return None;
+        }
+        Some(self.cpu.registers[Register(index)])
+    }
+
+    /// Write r0-r15 in the bank of the current mode, returning false for any
+    /// other index. Writing r15 continues execution at the new address.
+    pub fn set_register(&mut self, index: u32, value: u32) -> bool {
+        match index {
+            0...14 => self.cpu.registers[Register(index)] = value,
+            15 => self.cpu.jump(value),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn cpsr(&self) -> u32 {
+        self.cpu.cpsr.to_bits()
+    }
+}
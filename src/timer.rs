@@ -0,0 +1,302 @@
+use bit::{Bit, Bits, SetBits};
+use bus::{Read, Write};
+use interrupt_controller::{Input, InterruptController};
+use scheduler::{Event, Scheduler};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+// The number of cycles per count for each prescaler selection
+const PRESCALERS: [u64; 4] = [1, 64, 256, 1024];
+
+/// The four 16 bit timers.
+///
+/// Counters aren't stepped every cycle. Instead each timer records when its
+/// counter was last set, so its value can be found when read, and schedules
+/// an event for when it will overflow.
+pub struct Timers {
+    timers: [Timer; 4],
+    interrupts: Rc<RefCell<InterruptController>>,
+    scheduler: Rc<RefCell<Scheduler>>,
+}
+
+struct Timer {
+    reload: u16,
+    control: u16,
+    // The counter's value at `start`
+    counter: u16,
+    // The cycle from which the counter has been counting
+    start: u64,
+}
+
+impl Timer {
+    fn new() -> Timer {
+        Timer {
+            reload: 0,
+            control: 0,
+            counter: 0,
+            start: 0,
+        }
+    }
+
+    fn prescaler(&self) -> u64 {
+        PRESCALERS[self.control.bits(0..2) as usize]
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.control.bit(7)
+    }
+
+    // A timer in count-up mode is clocked by the overflow of the previous
+    // timer rather than its prescaler
+    fn is_count_up(&self) -> bool {
+        self.control.bit(2)
+    }
+
+    fn counter(&self, now: u64) -> u16 {
+        if self.is_enabled() && !self.is_count_up() {
+            let counts = (now - self.start) / self.prescaler();
+            self.counter.wrapping_add(counts as u16)
+        } else {
+            self.counter
+        }
+    }
+
+    fn overflow_time(&self) -> u64 {
+        self.start + (0x10000 - self.counter as u64) * self.prescaler()
+    }
+}
+
+impl Timers {
+    pub fn new(
+        interrupts: Rc<RefCell<InterruptController>>,
+        scheduler: Rc<RefCell<Scheduler>>,
+    ) -> Timers {
+        Timers {
+            timers: [Timer::new(), Timer::new(), Timer::new(), Timer::new()],
+            interrupts: interrupts,
+            scheduler: scheduler,
+        }
+    }
+
+    /// Overflow a timer at `time`, reloading it and cascading into any
+    /// following timers in count-up mode. Returns which timers overflowed.
+    pub fn overflow(&mut self, index: usize, time: u64) -> [bool; 4] {
+        let mut overflowed = [false; 4];
+        let mut index = index;
+        loop {
+            overflowed[index] = true;
+            {
+                let timer = &mut self.timers[index];
+                timer.counter = timer.reload;
+                timer.start = time;
+                if timer.control.bit(6) {
+                    self.interrupts.borrow_mut().assert(interrupt(index));
+                }
+            }
+            self.schedule_overflow(index);
+
+            let next = index + 1;
+            if next == self.timers.len() {
+                break;
+            }
+            let timer = &mut self.timers[next];
+            if !timer.is_enabled() || !timer.is_count_up() {
+                break;
+            }
+            timer.counter = timer.counter.wrapping_add(1);
+            if timer.counter != 0 {
+                break;
+            }
+            index = next;
+        }
+        overflowed
+    }
+
+    fn write_control(&mut self, index: usize, value: u16) {
+        let now = self.scheduler.borrow().now();
+        let timer = &mut self.timers[index];
+        let counter = timer.counter(now);
+        let was_enabled = timer.is_enabled();
+
+        // Timer 0 can't count up, as there is no timer before it
+        timer.control = if index == 0 {
+            value & 0xC3
+        } else {
+            value & 0xC7
+        };
+        // The counter is reloaded when the timer is started
+        timer.counter = if timer.is_enabled() && !was_enabled {
+            timer.reload
+        } else {
+            counter
+        };
+        timer.start = now;
+        self.schedule_overflow(index);
+    }
+
+    fn schedule_overflow(&mut self, index: usize) {
+        let timer = &self.timers[index];
+        let event = Event::TimerOverflow(index);
+        let mut scheduler = self.scheduler.borrow_mut();
+        scheduler.cancel(event);
+        if timer.is_enabled() && !timer.is_count_up() {
+            scheduler.schedule_at(timer.overflow_time(), event);
+        }
+    }
+}
+
+fn interrupt(index: usize) -> Input {
+    match index {
+        0 => Input::Timer0,
+        1 => Input::Timer1,
+        2 => Input::Timer2,
+        _ => Input::Timer3,
+    }
+}
+
+impl Read for Timers {
+    fn read_byte(&self, address: u32) -> u8 {
+        let halfword = self.read_halfword(address & !1);
+        if address.bit(0) {
+            (halfword >> 8) as u8
+        } else {
+            halfword as u8
+        }
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        let timer = &self.timers[(address as usize - 0x100) / 4];
+        if address.bit(1) {
+            timer.control
+        } else {
+            timer.counter(self.scheduler.borrow().now())
+        }
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        self.read_halfword(address) as u32
+            | (self.read_halfword(address + 2) as u32) << 16
+    }
+}
+
+// Writes to TMxCNT_L set the reload value, which is loaded into the counter
+// when the timer is started or overflows
+impl Write for Timers {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        let index = (address as usize - 0x100) / 4;
+        let mut halfword = if address.bit(1) {
+            self.timers[index].control
+        } else {
+            self.timers[index].reload
+        };
+        if address.bit(0) {
+            halfword.set_bits(8..16, value as u16);
+        } else {
+            halfword.set_bits(0..8, value as u16);
+        }
+        self.write_halfword(address & !1, halfword);
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        let index = (address as usize - 0x100) / 4;
+        if address.bit(1) {
+            self.write_control(index, value);
+        } else {
+            self.timers[index].reload = value;
+        }
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        self.write_halfword(address, value as u16);
+        self.write_halfword(address + 2, (value >> 16) as u16);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timers() -> (
+        Timers,
+        Rc<RefCell<InterruptController>>,
+        Rc<RefCell<Scheduler>>,
+    ) {
+        let interrupts = Rc::new(RefCell::new(InterruptController::new()));
+        let scheduler = Rc::new(RefCell::new(Scheduler::new()));
+        let timers = Timers::new(Rc::clone(&interrupts), Rc::clone(&scheduler));
+        (timers, interrupts, scheduler)
+    }
+
+    // Move time forward and take timer 0's overflow if it has become due
+    fn overflow_due(scheduler: &RefCell<Scheduler>, cycles: u64) -> bool {
+        let mut scheduler = scheduler.borrow_mut();
+        scheduler.advance(cycles);
+        let event = scheduler.pop_due().map(|(event, _)| event);
+        event == Some(Event::TimerOverflow(0))
+    }
+
+    #[test]
+    fn counts_at_the_prescaler_rate() {
+        let (mut timers, _, scheduler) = timers();
+        timers.write_halfword(0x100, 0x1000);
+        // Enabled, counting every 64 cycles
+        timers.write_halfword(0x102, 0x81);
+        assert_eq!(timers.read_halfword(0x100), 0x1000);
+        scheduler.borrow_mut().advance(64 * 10 + 63);
+        assert_eq!(timers.read_halfword(0x100), 0x100A);
+    }
+
+    #[test]
+    fn overflow_is_scheduled_and_reloads() {
+        let (mut timers, interrupts, scheduler) = timers();
+        timers.write_halfword(0x100, 0xFFF0);
+        // Enabled with its overflow interrupt
+        timers.write_halfword(0x102, 0xC0);
+        assert!(!overflow_due(&scheduler, 15));
+        assert!(overflow_due(&scheduler, 1));
+
+        assert_eq!(timers.overflow(0, 16), [true, false, false, false]);
+        assert_eq!(timers.read_halfword(0x100), 0xFFF0);
+        assert_eq!(interrupts.borrow().read_halfword(0x202), 1 << 3);
+        // The next overflow is counted from the reload value
+        assert!(!overflow_due(&scheduler, 15));
+        assert!(overflow_due(&scheduler, 1));
+    }
+
+    #[test]
+    fn overflow_cascades_through_count_up_timers() {
+        let (mut timers, interrupts, _) = timers();
+        timers.write_halfword(0x102, 0x80);
+        // Timer 1 is about to overflow, and timer 2 won't yet
+        timers.write_halfword(0x104, 0xFFFF);
+        timers.write_halfword(0x106, 0xC4);
+        timers.write_halfword(0x108, 0xFFFE);
+        timers.write_halfword(0x10A, 0xC4);
+
+        assert_eq!(timers.overflow(0, 0), [true, true, false, false]);
+        assert_eq!(timers.read_halfword(0x104), 0xFFFF);
+        assert_eq!(timers.read_halfword(0x108), 0xFFFF);
+        assert_eq!(interrupts.borrow().read_halfword(0x202), 1 << 4);
+
+        assert_eq!(timers.overflow(0, 0), [true, true, true, false]);
+        assert_eq!(timers.read_halfword(0x108), 0xFFFE);
+        assert_eq!(interrupts.borrow().read_halfword(0x202), 3 << 4);
+    }
+
+    #[test]
+    fn count_up_timers_are_not_scheduled() {
+        let (mut timers, _, scheduler) = timers();
+        timers.write_halfword(0x106, 0x84);
+        assert_eq!(scheduler.borrow().cycles_until_next(), None);
+        scheduler.borrow_mut().advance(0x10000);
+        assert_eq!(timers.read_halfword(0x104), 0);
+    }
+
+    #[test]
+    fn timer_0_cannot_count_up() {
+        let (mut timers, _, scheduler) = timers();
+        timers.write_halfword(0x102, 0x84);
+        assert_eq!(timers.read_halfword(0x102), 0x80);
+        assert_eq!(scheduler.borrow().cycles_until_next(), Some(0x10000));
+    }
+}
@@ -0,0 +1,116 @@
+use png;
+use ppu::{HEIGHT, WIDTH};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+/// A destination for the frames the emulator displays, such as a window or
+/// image files.
+pub trait VideoSink {
+    /// Take a completed frame, as rows of 0xRRGGBB pixels.
+    fn push_frame(&mut self, frame: &[u32]);
+
+    /// The first error the sink has met, if any, which `finish` will
+    /// return.
+    fn error(&self) -> Option<&io::Error> {
+        None
+    }
+
+    /// Finish the output once no more frames will be pushed, returning the
+    /// first error the sink met.
+    fn finish(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ImageFormat {
+    Png,
+    Ppm,
+}
+
+/// A video sink which writes frames to numbered image files in a
+/// directory, either every frame or a single one. Nothing more is written
+/// after an error, which `finish` returns.
+pub struct FrameDumper {
+    directory: PathBuf,
+    format: ImageFormat,
+    // The only frame to write, if not every frame
+    only: Option<u64>,
+    // The number of the next frame, counted from 0
+    frame: u64,
+    error: Option<io::Error>,
+}
+
+impl FrameDumper {
+    pub fn new(
+        directory: PathBuf,
+        format: ImageFormat,
+        only: Option<u64>,
+    ) -> io::Result<FrameDumper> {
+        fs::create_dir_all(&directory)?;
+        Ok(FrameDumper {
+            directory: directory,
+            format: format,
+            only: only,
+            frame: 0,
+            error: None,
+        })
+    }
+
+    fn write_frame(&self, number: u64, frame: &[u32]) -> io::Result<()> {
+        let extension = match self.format {
+            ImageFormat::Png => "png",
+            ImageFormat::Ppm => "ppm",
+        };
+        let path = self
+            .directory
+            .join(format!("frame{:06}.{}", number, extension));
+        let mut file = BufWriter::new(File::create(path)?);
+        match self.format {
+            ImageFormat::Png => png::write(&mut file, WIDTH, HEIGHT, frame)?,
+            ImageFormat::Ppm => write_ppm(&mut file, frame)?,
+        }
+        file.flush()
+    }
+}
+
+impl VideoSink for FrameDumper {
+    fn push_frame(&mut self, frame: &[u32]) {
+        let number = self.frame;
+        self.frame += 1;
+        let wanted = match self.only {
+            Some(only) => only == number,
+            None => true,
+        };
+        if wanted && self.error.is_none() {
+            if let Err(err) = self.write_frame(number, frame) {
+                let message = format!("Frame {}: {}", number, err);
+                self.error = Some(io::Error::new(err.kind(), message));
+            }
+        }
+    }
+
+    fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+}
+
+// A binary PPM is a short text header followed by the RGB bytes
+fn write_ppm<W: Write>(writer: &mut W, frame: &[u32]) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", WIDTH, HEIGHT)?;
+    let mut data = Vec::with_capacity(3 * frame.len());
+    for pixel in frame {
+        data.push((pixel >> 16) as u8);
+        data.push((pixel >> 8) as u8);
+        data.push(*pixel as u8);
+    }
+    writer.write_all(&data)
+}
@@ -0,0 +1,236 @@
+use bit::{Bit, Bits, SetBits};
+use bus::{Read, Width, Write};
+use std::cell::Cell;
+
+// The first access to each game pak wait state region, selected by WAITCNT
+const NONSEQUENTIAL_WAITS: [u32; 4] = [4, 3, 2, 8];
+
+// The prefetch buffer holds up to eight halfwords
+const PREFETCH_CAPACITY: u32 = 8;
+
+/// The WAITCNT register, which sets the wait states of the game pak, and the
+/// game pak prefetch buffer it enables.
+///
+/// The timing of every memory region is decided here, so the memory map can
+/// charge each access the cycles it takes.
+pub struct WaitControl {
+    waitcnt: u16,
+    prefetch: Cell<Prefetch>,
+}
+
+/// The state of the prefetch buffer, which reads ahead from the game pak
+/// while the CPU isn't using it so sequential code fetches can be served in
+/// a single cycle.
+#[derive(Clone, Copy)]
+struct Prefetch {
+    active: bool,
+    // The address of the oldest halfword in the buffer
+    head: u32,
+    // The number of halfwords in the buffer
+    count: u32,
+    // Cycles spent fetching the halfword after the end of the buffer
+    progress: u32,
+}
+
+impl Prefetch {
+    fn new() -> Prefetch {
+        Prefetch {
+            active: false,
+            head: 0,
+            count: 0,
+            progress: 0,
+        }
+    }
+}
+
+impl WaitControl {
+    pub fn new() -> WaitControl {
+        WaitControl {
+            waitcnt: 0,
+            prefetch: Cell::new(Prefetch::new()),
+        }
+    }
+
+    /// The number of cycles taken by a data access. Accesses wider than the
+    /// bus of a region are split into several accesses, the later of which
+    /// are sequential.
+    pub fn access_cycles(
+        &self,
+        address: u32,
+        width: Width,
+        sequential: bool,
+    ) -> u32 {
+        match address >> 24 {
+            // On-board work RAM has a 16 bit bus and two wait states
+            0x2 => match width {
+                Width::Word => 6,
+                _ => 3,
+            },
+            // Palette RAM and VRAM have a 16 bit bus
+            0x5 | 0x6 => match width {
+                Width::Word => 2,
+                _ => 1,
+            },
+            0x8...0xD => {
+                let region = (address >> 25) - 4;
+                // The game pak can't continue a burst across a 128 KiB
+                // boundary
+                let sequential = sequential && address & 0x1FFFF != 0;
+                let first = 1 + if sequential {
+                    self.sequential_waits(region)
+                } else {
+                    self.nonsequential_waits(region)
+                };
+                match width {
+                    Width::Word => first + 1 + self.sequential_waits(region),
+                    _ => first,
+                }
+            }
+            // SRAM has an 8 bit bus, but only byte accesses are meaningful
+            0xE | 0xF => {
+                1 + NONSEQUENTIAL_WAITS[self.waitcnt.bits(0..2) as usize]
+            }
+            // The BIOS, on-chip work RAM, I/O and OAM have a 32 bit bus and
+            // no wait states
+            _ => 1,
+        }
+    }
+
+    /// The number of cycles taken by an instruction fetch from the game pak,
+    /// which is served by the prefetch buffer if it has read ahead far
+    /// enough.
+    pub fn fetch_cycles(
+        &self,
+        address: u32,
+        width: Width,
+        sequential: bool,
+    ) -> u32 {
+        if !self.prefetch_enabled() {
+            return self.access_cycles(address, width, sequential);
+        }
+
+        let halfwords = width.bytes() / 2;
+        let mut prefetch = self.prefetch.get();
+        let cycles = if prefetch.active && prefetch.head == address
+            && prefetch.count >= halfwords
+        {
+            prefetch.head = prefetch.head.wrapping_add(halfwords * 2);
+            prefetch.count -= halfwords;
+            1
+        } else {
+            // The CPU fetches the instruction itself, and the buffer starts
+            // reading ahead from the instruction after it
+            prefetch = Prefetch {
+                active: true,
+                head: address.wrapping_add(halfwords * 2),
+                count: 0,
+                progress: 0,
+            };
+            self.access_cycles(address, width, sequential)
+        };
+        self.prefetch.set(prefetch);
+        cycles
+    }
+
+    /// Let the prefetch buffer use the game pak for a number of cycles in
+    /// which the CPU isn't accessing it.
+    pub fn idle(&self, cycles: u32) {
+        let mut prefetch = self.prefetch.get();
+        if !self.prefetch_enabled() || !prefetch.active {
+            return;
+        }
+
+        let tail = prefetch.head.wrapping_add(prefetch.count * 2);
+        let halfword_cycles = 1 + self.sequential_waits((tail >> 25) - 4);
+        prefetch.progress += cycles;
+        while prefetch.count < PREFETCH_CAPACITY
+            && prefetch.progress >= halfword_cycles
+        {
+            prefetch.count += 1;
+            prefetch.progress -= halfword_cycles;
+        }
+        if prefetch.count == PREFETCH_CAPACITY {
+            prefetch.progress = 0;
+        }
+        self.prefetch.set(prefetch);
+    }
+
+    /// Empty the prefetch buffer, as a data access to the game pak
+    /// interrupts it.
+    pub fn stop_prefetch(&self) {
+        self.prefetch.set(Prefetch::new());
+    }
+
+    fn prefetch_enabled(&self) -> bool {
+        self.waitcnt.bit(14)
+    }
+
+    fn nonsequential_waits(&self, region: u32) -> u32 {
+        let offset = 2 + region as u8 * 3;
+        NONSEQUENTIAL_WAITS[self.waitcnt.bits(offset..offset + 2) as usize]
+    }
+
+    fn sequential_waits(&self, region: u32) -> u32 {
+        let fast = self.waitcnt.bit(4 + region as u8 * 3);
+        match (region, fast) {
+            (_, true) => 1,
+            (0, false) => 2,
+            (1, false) => 4,
+            (_, false) => 8,
+        }
+    }
+}
+
+impl Read for WaitControl {
+    fn read_byte(&self, address: u32) -> u8 {
+        match address {
+            0x204 => self.waitcnt as u8,
+            0x205 => (self.waitcnt >> 8) as u8,
+            0x206 | 0x207 => 0,
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_halfword(&self, address: u32) -> u16 {
+        match address {
+            0x204 => self.waitcnt,
+            0x206 => 0,
+            _ => unreachable!(),
+        }
+    }
+
+    fn read_word(&self, address: u32) -> u32 {
+        match address {
+            0x204 => self.waitcnt as u32,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// The game pak type flag in bit 15 is read-only, and reads as 0 for a GBA
+// game pak
+impl Write for WaitControl {
+    fn write_byte(&mut self, address: u32, value: u8) {
+        match address {
+            0x204 => self.waitcnt.set_bits(0..8, value as u16),
+            0x205 => self.waitcnt.set_bits(8..15, value as u16),
+            0x206 | 0x207 => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_halfword(&mut self, address: u32, value: u16) {
+        match address {
+            0x204 => self.waitcnt = value & 0x7FFF,
+            0x206 => {}
+            _ => unreachable!(),
+        }
+    }
+
+    fn write_word(&mut self, address: u32, value: u32) {
+        match address {
+            0x204 => self.waitcnt = value as u16 & 0x7FFF,
+            _ => unreachable!(),
+        }
+    }
+}
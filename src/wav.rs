@@ -0,0 +1,154 @@
+use audio::AudioSink;
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+// The size of the RIFF header and format chunk before the sample data
+const HEADER_SIZE: u32 = 44;
+
+/// An audio sink which records 16 bit stereo PCM to a WAV file.
+///
+/// The sizes in the header are brought up to date after each block, so the
+/// file is complete even if the emulator is killed rather than exiting.
+/// Nothing more is written after an error, which `finish` returns.
+pub struct WavWriter {
+    file: BufWriter<File>,
+    sample_rate: u32,
+    // Bytes of sample data written
+    data_size: u32,
+    error: Option<io::Error>,
+}
+
+impl WavWriter {
+    pub fn create(path: &Path, sample_rate: u32) -> io::Result<WavWriter> {
+        let mut writer = WavWriter {
+            file: BufWriter::new(File::create(path)?),
+            sample_rate: sample_rate,
+            data_size: 0,
+            error: None,
+        };
+        writer.write_header()?;
+        Ok(writer)
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        let file = &mut self.file;
+        file.write_all(b"RIFF")?;
+        file.write_u32::<LittleEndian>(HEADER_SIZE - 8 + self.data_size)?;
+        file.write_all(b"WAVE")?;
+
+        file.write_all(b"fmt ")?;
+        file.write_u32::<LittleEndian>(16)?;
+        // Uncompressed PCM, in two channels of 16 bit samples
+        file.write_u16::<LittleEndian>(1)?;
+        file.write_u16::<LittleEndian>(2)?;
+        file.write_u32::<LittleEndian>(self.sample_rate)?;
+        file.write_u32::<LittleEndian>(self.sample_rate * 4)?;
+        file.write_u16::<LittleEndian>(4)?;
+        file.write_u16::<LittleEndian>(16)?;
+
+        file.write_all(b"data")?;
+        file.write_u32::<LittleEndian>(self.data_size)
+    }
+
+    fn write_frames(&mut self, frames: &[[i16; 2]]) -> io::Result<()> {
+        for frame in frames {
+            self.file.write_i16::<LittleEndian>(frame[0])?;
+            self.file.write_i16::<LittleEndian>(frame[1])?;
+        }
+        self.data_size += frames.len() as u32 * 4;
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.write_header()?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+}
+
+impl AudioSink for WavWriter {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn push(&mut self, frames: &[[i16; 2]]) {
+        if self.error.is_none() {
+            if let Err(err) = self.write_frames(frames) {
+                self.error = Some(err);
+            }
+        }
+    }
+
+    fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.file.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use byteorder::ByteOrder;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::process;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let file = format!("gba-wav-test-{}-{}.wav", process::id(), name);
+        std::env::temp_dir().join(file)
+    }
+
+    // The RIFF size and the data chunk size from a WAV file's header
+    fn header_sizes(bytes: &[u8]) -> (u32, u32) {
+        (
+            LittleEndian::read_u32(&bytes[4..8]),
+            LittleEndian::read_u32(&bytes[40..44]),
+        )
+    }
+
+    #[test]
+    fn a_new_file_has_an_empty_data_chunk() {
+        let path = temp_path("empty");
+        let mut writer = WavWriter::create(&path, 48000).unwrap();
+        writer.finish().unwrap();
+        drop(writer);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE as usize);
+        assert_eq!(&bytes[0..4], b"RIFF");
+        assert_eq!(&bytes[8..16], b"WAVEfmt ");
+        assert_eq!(LittleEndian::read_u32(&bytes[24..28]), 48000);
+        assert_eq!(LittleEndian::read_u32(&bytes[28..32]), 48000 * 4);
+        assert_eq!(&bytes[36..40], b"data");
+        assert_eq!(header_sizes(&bytes), (36, 0));
+    }
+
+    #[test]
+    fn the_header_is_rewritten_after_each_block() {
+        let path = temp_path("blocks");
+        let mut writer = WavWriter::create(&path, 32768).unwrap();
+
+        writer.push(&[[1, -1], [2, -2]]);
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(header_sizes(&bytes), (36 + 8, 8));
+
+        writer.push(&[[0x1234, -0x1234]]);
+        writer.finish().unwrap();
+        drop(writer);
+
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER_SIZE as usize + 12);
+        assert_eq!(header_sizes(&bytes), (36 + 12, 12));
+        let samples: Vec<i16> =
+            bytes[44..].chunks(2).map(LittleEndian::read_i16).collect();
+        assert_eq!(samples, vec![1, -1, 2, -2, 0x1234, -0x1234]);
+    }
+}